@@ -0,0 +1,214 @@
+//! Computation of unified diffs between two versions of a file.
+
+use std::cmp;
+
+
+/// An operation transforming the old lines into the new lines, alongside the index of
+/// the old and new lines it applies to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Equal(usize, usize),
+    Delete(usize, usize),
+    Insert(usize, usize)
+}
+
+impl Op {
+    fn indices(self) -> (usize, usize) {
+        match self {
+            Op::Equal(a, b) | Op::Delete(a, b) | Op::Insert(a, b) => (a, b)
+        }
+    }
+}
+
+/// Return the unified diff between the given sources, with the given number of context
+/// lines around changes, or an empty string if they are equal.
+pub fn unified_diff(old: &str, new: &str, path: &str, context: usize) -> String {
+    let old_lines = lines(old);
+    let new_lines = lines(new);
+    let ops = diff_lines(&old_lines, &new_lines);
+
+    let mut result = String::new();
+    let changes: Vec<_> = ops.iter()
+                             .enumerate()
+                             .filter(|&(_, op)| !matches!(*op, Op::Equal(..)))
+                             .map(|(i, _)| i)
+                             .collect();
+
+    if changes.is_empty() {
+        return result
+    }
+
+    result.push_str(&format!("--- {}\n+++ {}\n", path, path));
+
+    // Group changes that are close enough to share their context into hunks
+    let mut i = 0;
+
+    while i < changes.len() {
+        let mut j = i;
+
+        while j + 1 < changes.len() && changes[j + 1] - changes[j] <= 2 * context + 1 {
+            j += 1;
+        }
+
+        let start = changes[i].saturating_sub(context);
+        let end = cmp::min(changes[j] + context + 1, ops.len());
+        let hunk = &ops[start..end];
+
+        let (old_start, new_start) = hunk[0].indices();
+        let old_count = hunk.iter().filter(|op| !matches!(**op, Op::Insert(..))).count();
+        let new_count = hunk.iter().filter(|op| !matches!(**op, Op::Delete(..))).count();
+
+        // Empty ranges refer to the line before them
+        result.push_str(&format!("@@ -{} +{} @@\n",
+                                 hunk_range(old_start, old_count), hunk_range(new_start, new_count)));
+
+        for op in hunk {
+            let (prefix, line) = match *op {
+                Op::Equal(a, _) => (' ', old_lines[a]),
+                Op::Delete(a, _) => ('-', old_lines[a]),
+                Op::Insert(_, b) => ('+', new_lines[b])
+            };
+
+            result.push(prefix);
+            result.push_str(line);
+
+            if !line.ends_with('\n') {
+                result.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+
+        i = j + 1;
+    }
+
+    result
+}
+
+/// Format the range of a hunk, given the index of its first line and its length.
+fn hunk_range(start: usize, count: usize) -> String {
+    match count {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, count)
+    }
+}
+
+/// Split the given source into lines, keeping their terminating new-line character.
+fn lines(source: &str) -> Vec<&str> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+
+    while start < source.len() {
+        let end = source[start..].find('\n').map(|i| start + i + 1).unwrap_or(source.len());
+
+        lines.push(&source[start..end]);
+        start = end;
+    }
+
+    lines
+}
+
+/// Compute the operations transforming the old lines into the new lines, using the
+/// longest common subsequence of lines that differ between both.
+fn diff_lines(old: &[&str], new: &[&str]) -> Vec<Op> {
+    // Edits are usually small, so we strip the common prefix and suffix first
+    let prefix = old.iter().zip(new).take_while(|&(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev()
+                              .zip(new[prefix..].iter().rev())
+                              .take_while(|&(a, b)| a == b)
+                              .count();
+
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    // lcs[i][j] is the length of the longest common subsequence of old_mid[i..] and new_mid[j..]
+    let mut lcs = vec![vec![0u32; new_mid.len() + 1]; old_mid.len() + 1];
+
+    for i in (0..old_mid.len()).rev() {
+        for j in (0..new_mid.len()).rev() {
+            lcs[i][j] = if old_mid[i] == new_mid[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                cmp::max(lcs[i + 1][j], lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut ops: Vec<_> = (0..prefix).map(|i| Op::Equal(i, i)).collect();
+    let (mut i, mut j) = (0, 0);
+
+    while i < old_mid.len() || j < new_mid.len() {
+        if i < old_mid.len() && j < new_mid.len() && old_mid[i] == new_mid[j] {
+            ops.push(Op::Equal(prefix + i, prefix + j));
+            i += 1;
+            j += 1;
+        } else if i < old_mid.len() && (j == new_mid.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            ops.push(Op::Delete(prefix + i, prefix + j));
+            i += 1;
+        } else {
+            ops.push(Op::Insert(prefix + i, prefix + j));
+            j += 1;
+        }
+    }
+
+    ops.extend((0..suffix).map(|k| Op::Equal(old.len() - suffix + k, new.len() - suffix + k)));
+    ops
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_equal() {
+        assert_eq!(unified_diff("a\nb\n", "a\nb\n", "file.nix", 3), "");
+    }
+
+    #[test]
+    fn test_hunks() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n";
+        let new = "1\ntwo\n3\n4\n5\n6\n7\n8\n9\n10\n12\n13\n";
+
+        assert_eq!(unified_diff(old, new, "file.nix", 1), "\
+--- file.nix
++++ file.nix
+@@ -1,3 +1,3 @@
+ 1
+-2
++two
+ 3
+@@ -10,3 +10,3 @@
+ 10
+-11
+ 12
++13
+");
+
+        assert_eq!(unified_diff(old, new, "file.nix", 4), "\
+--- file.nix
++++ file.nix
+@@ -1,12 +1,12 @@
+ 1
+-2
++two
+ 3
+ 4
+ 5
+ 6
+ 7
+ 8
+ 9
+ 10
+-11
+ 12
++13
+");
+    }
+
+    #[test]
+    fn test_edges() {
+        assert_eq!(unified_diff("", "a\n", "f", 3), "--- f\n+++ f\n@@ -0,0 +1 @@\n+a\n");
+        assert_eq!(unified_diff("a\nb", "a\nc", "f", 3),
+                   "--- f\n+++ f\n@@ -1,2 +1,2 @@\n a\n-b\n\\ No newline at end of file\n+c\n\\ No newline at end of file\n");
+    }
+}
@@ -0,0 +1,651 @@
+//! Documents, which hold the source of a file alongside its AST, and which are queried and
+//! modified through paths.
+
+use std::cmp::Reverse;
+use std::fs::File;
+use std::io::Read;
+use std::ops::Range;
+use std::path::Path as FilePath;
+
+use rnix::{SyntaxNode, AST};
+use rnix::SyntaxKind::*;
+
+use error::Error;
+use json::Json;
+use path::{self, Match, Path};
+use {eval, instantiate};
+use super::{all_definitions, dedent, find_all, find_expr, find_insertion_point, find_matches, format_key, indent_unit,
+            insert_entry, insert_into_empty, is_ambiguous, leading_whitespace, line_end, line_ending, line_indent,
+            locate, merge_entries, no_match, parse, removal_range, select_definitions, span_range, token,
+            token_range, unsupported, Options};
+
+
+/// A value matched by a path.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Value {
+    /// The fully-qualified path of the value, such as `fileSystems[0].device`.
+    pub path: String,
+
+    /// The Nix source of the value.
+    pub source: String
+}
+
+impl Value {
+    /// Convert the value to JSON, with expressions that cannot be converted represented as
+    /// `{ "$nix": "<source>" }`.
+    pub fn to_json(&self) -> Result<Json, Error> {
+        Json::from_source(&self.source)
+    }
+}
+
+/// The location of a definition of a value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Location {
+    /// The fully-qualified path of the value.
+    pub path: String,
+
+    /// The line of the definition, starting at 1.
+    pub line: usize,
+
+    /// The column of the definition, starting at 1.
+    pub column: usize,
+
+    /// The range of bytes spanned by the key of the definition, or `None` if the value is
+    /// not defined by an entry, like list items.
+    pub key: Option<Range<usize>>,
+
+    /// The range of bytes spanned by the value.
+    pub value: Range<usize>
+}
+
+/// A Nix file, whose values can be queried and modified while keeping its formatting and
+/// comments intact.
+pub struct Document {
+    source: String,
+    ast: AST,
+
+    /// Options affecting the way paths are resolved.
+    pub options: Options
+}
+
+impl Document {
+    /// Parse the given source, failing if it is not a valid Nix expression.
+    pub fn parse(source: String) -> Result<Document, Error> {
+        let ast = parse(&source, "input file")?;
+
+        Ok(Document { source, ast, options: Options::default() })
+    }
+
+    /// Read and parse the given file.
+    pub fn open(file: &FilePath) -> Result<Document, Error> {
+        let mut source = String::new();
+
+        File::open(file)
+            .map_err(|err| Error::Io(format!("Unable to open file '{}': {}.", file.display(), err)))?
+            .read_to_string(&mut source)
+            .map_err(|err| Error::Io(format!("Unable to read input file: {}.", err)))?;
+
+        let mut document = Document::parse(source)?;
+
+        document.options.file = file.to_path_buf();
+
+        Ok(document)
+    }
+
+    /// Return the source of the document.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Return the source of the document, consuming it.
+    pub fn into_source(self) -> String {
+        self.source
+    }
+
+    /// Return the value at the given path, failing if it matches several values.
+    pub fn get(&self, path: &Path) -> Result<Value, Error> {
+        let mut values = self.get_all(path)?;
+
+        if values.len() > 1 {
+            return Err(Error::Ambiguous(format!("Path '{}' matches several values.", path)))
+        }
+
+        Ok(values.remove(0))
+    }
+
+    /// Return all the values matching the given path, with one value per definition for
+    /// values defined several times.
+    pub fn get_all(&self, path: &Path) -> Result<Vec<Value>, Error> {
+        let mut values = Vec::new();
+
+        for m in self.definitions(path)? {
+            let source = match m.node() {
+                Some(node) => dedent(&self.source, &node),
+                None => merge_entries(&m.entries, &self.source, &m.path)?
+            };
+
+            values.push(Value { path: m.path, source });
+        }
+
+        Ok(values)
+    }
+
+    /// Return all the values matching the given path like `get_all`, evaluated statically.
+    /// They must only depend on literals and on variables defined in the document.
+    pub fn evaluate(&self, path: &Path) -> Result<Vec<Value>, Error> {
+        let unit = indent_unit(&self.source);
+        let mut values = Vec::new();
+
+        for m in self.definitions(path)? {
+            let value = eval::evaluate(&m, &self.source)
+                .map_err(|err| Error::Eval(format!("Value at path '{}' cannot be evaluated statically: {}.", m.path, err)))?;
+
+            values.push(Value { path: m.path, source: value.to_nix("", &unit) });
+        }
+
+        Ok(values)
+    }
+
+    /// Return all the values matching the given path like `get_all`, evaluated by the given
+    /// `nix-instantiate` command. The arguments of the functions around them are replaced by
+    /// stubs, and relative paths are resolved from the directory of `options.file`.
+    pub fn nix_evaluate(&self, path: &Path, command: &str) -> Result<Vec<Value>, Error> {
+        let unit = indent_unit(&self.source);
+        let dir = self.options.file.parent().unwrap_or_else(|| FilePath::new(""));
+        let mut values = Vec::new();
+
+        for m in self.definitions(path)? {
+            let source = match m.node() {
+                Some(node) => self.source[span_range(&node)].to_string(),
+                None => merge_entries(&m.entries, &self.source, &m.path)?
+            };
+            let value = instantiate::nix_eval(&m.entries[0].1, &source, &self.source, command, dir)?;
+
+            values.push(Value { path: m.path, source: value.to_nix("", &unit) });
+        }
+
+        Ok(values)
+    }
+
+    /// Return the locations of the definitions of the values matching the given path. Values
+    /// defined several times are all listed, unless a single definition was chosen.
+    pub fn locate(&self, path: &Path) -> Result<Vec<Location>, Error> {
+        let (root, content) = (&self.ast.node(), &self.source);
+        let options = all_definitions(&self.options);
+        let mut locations = Vec::new();
+
+        for m in find_all(root, path, content, &options)? {
+            for (key, value) in select_definitions(m.clone(), content, &options)?.entries {
+                // Values that are not defined by an entry, such as list items, have no key
+                let key_range = match value.parent() {
+                    Some(ref entry) if entry.kind() == NODE_KEY_VALUE => entry.children().next().map(|key| span_range(&key)),
+                    Some(ref entry) if entry.kind() == NODE_INHERIT => Some(span_range(&value)),
+                    _ => None
+                };
+                let value_range = span_range(&value);
+                let (line, column) = locate(content, key_range.as_ref().unwrap_or(&value_range).start);
+
+                let path = match (m.path.is_empty(), key.is_empty()) {
+                    (_, true) => m.path.clone(),
+                    (true, false) => format_key(&key, content),
+                    (false, false) => format!("{}.{}", m.path, format_key(&key, content))
+                };
+
+                locations.push(Location { path, line, column, key: key_range, value: value_range });
+            }
+        }
+
+        Ok(locations)
+    }
+
+    /// Return the values defined in the document, or in the value at the given path,
+    /// flattening nested attribute sets. Values defined several times are all listed, unless
+    /// a single definition was chosen.
+    pub fn paths(&self, path: Option<&Path>) -> Result<Vec<Value>, Error> {
+        let (root, content) = (&self.ast.node(), &self.source);
+        let matches = match path {
+            Some(path) => find_all(root, path, content, &self.options)?,
+            None => path::resolve(root, &[], content)?
+        };
+        let options = all_definitions(&self.options);
+        let mut values = Vec::new();
+
+        for m in matches {
+            for m in path::flatten(m, content)? {
+                let m = select_definitions(m, content, &options)?;
+
+                for (_, node) in m.entries {
+                    values.push(Value { path: m.path.clone(), source: dedent(content, &node) });
+                }
+            }
+        }
+
+        Ok(values)
+    }
+
+    /// Set the value at the given path to the given Nix expression, replacing every value it
+    /// matches, or inserting it if it does not exist.
+    pub fn set(&mut self, path: &Path, value: &str) -> Result<(), Error> {
+        self.set_with(path, &|_| value.to_string())
+    }
+
+    /// Set the value at the given path like `set`, converting the given JSON value to Nix.
+    pub fn set_json(&mut self, path: &Path, value: &Json) -> Result<(), Error> {
+        let unit = indent_unit(&self.source);
+
+        self.set_with(path, &|indent| value.to_nix(indent, &unit))
+    }
+
+    /// Insert the given Nix expression at the given path, failing if it already exists.
+    pub fn insert(&mut self, path: &Path, value: &str) -> Result<(), Error> {
+        parse(value, "value")?;
+
+        if !find_matches(&self.ast.node(), path.segments(), &self.source, &self.options)?.is_empty() {
+            return Err(Error::InvalidValue(format!("Path '{}' is already defined.", path)))
+        }
+
+        self.insert_with(path, &|_| value.to_string())
+    }
+
+    /// Delete the values matching the given path, and the parent attribute sets that become
+    /// empty if `prune` is set.
+    pub fn delete(&mut self, path: &Path, prune: bool) -> Result<(), Error> {
+        self.edit(|root, content, options| {
+            let mut entries = Vec::new();
+
+            for m in find_all(root, path, content, options)? {
+                entries.extend(select_definitions(m, content, options)?.entries);
+            }
+
+            // Find the entries that hold the matched values, which are the values themselves
+            // for list elements
+            let mut to_delete = Vec::new();
+
+            for (_, value) in entries {
+                match value.parent() {
+                    Some(ref entry) if entry.kind() == NODE_KEY_VALUE => to_delete.push(entry.clone()),
+                    Some(ref list) if list.kind() == NODE_LIST => to_delete.push(value),
+                    Some(ref inherit) if inherit.kind() == NODE_INHERIT => to_delete.push(value),
+                    _ => return Err(Error::InvalidValue(format!("Path '{}' does not refer to an attribute.", path)))
+                }
+            }
+
+            // Inherited names are removed from their `inherit`, unless it has no names left
+            for node in to_delete.clone() {
+                if let Some(inherit) = node.parent().filter(|parent| parent.kind() == NODE_INHERIT) {
+                    let is_empty = inherit.children()
+                                          .filter(|child| child.kind() == NODE_IDENT)
+                                          .all(|ident| to_delete.contains(&ident));
+
+                    if is_empty && !to_delete.contains(&inherit) {
+                        to_delete.push(inherit);
+                    }
+                }
+            }
+
+            if prune {
+                // Also delete entries whose value is a set that only contains deleted entries
+                let mut i = 0;
+
+                while i < to_delete.len() {
+                    let parent_set = to_delete[i].parent();
+                    let parent_entry = parent_set.as_ref().and_then(SyntaxNode::parent);
+
+                    if let (Some(set), Some(entry)) = (parent_set, parent_entry) {
+                        let is_empty = set.kind() == NODE_ATTR_SET &&
+                                       set.children()
+                                          .filter(|node| node.kind() == NODE_KEY_VALUE || node.kind() == NODE_INHERIT)
+                                          .all(|node| to_delete.contains(&node));
+
+                        if is_empty && entry.kind() == NODE_KEY_VALUE && !to_delete.contains(&entry) {
+                            to_delete.push(entry);
+                        }
+                    }
+
+                    i += 1;
+                }
+            }
+
+            // Remove entries from last to first, ignoring entries nested in other deleted entries
+            let mut ranges: Vec<_> = to_delete.iter()
+                                              .map(|node| removal_range(content, span_range(node)))
+                                              .collect();
+
+            ranges.sort_by_key(|range| (range.start, Reverse(range.end)));
+            ranges.dedup_by(|inner, outer| inner.end <= outer.end);
+
+            for range in ranges.into_iter().rev() {
+                content.replace_range(range, "");
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Add the given Nix expressions to the list at the given path, at its start if `prepend`
+    /// is set, and failing if one of them is already in the list if `unique` is set.
+    pub fn list_add<S: AsRef<str>>(&mut self, path: &Path, values: &[S], prepend: bool, unique: bool) -> Result<(), Error> {
+        let values: Vec<_> = values.iter().map(|value| value.as_ref().to_string()).collect();
+
+        self.edit(|root, content, options| {
+            let (list, items) = find_list(root, path, content, options)?;
+
+            for value in &values {
+                parse(value, "value")?;
+            }
+
+            if unique {
+                if let Some(value) = values.iter().find(|value| find_item(&items, content, value).is_some()) {
+                    return Err(Error::InvalidValue(format!("Value '{}' is already in the list.", value)))
+                }
+            }
+
+            let anchor = if prepend { items.first() } else { items.last() };
+
+            match anchor {
+                Some(anchor) => {
+                    let range = span_range(anchor);
+
+                    // Keep the layout of the list, which has either one element per line or
+                    // all elements inline
+                    let (separator, end) = match line_indent(content, range.start) {
+                        Some(indent) => (format!("{}{}", line_ending(content), indent), line_end(content, range.end)),
+                        None => (" ".to_string(), range.end)
+                    };
+                    let values = values.join(&separator);
+
+                    if prepend {
+                        content.insert_str(range.start, &format!("{}{}", values, separator));
+                    } else {
+                        content.insert_str(end, &format!("{}{}", separator, values));
+                    }
+                },
+
+                None => {
+                    let (open, close) = match (token(&list, TOKEN_SQUARE_B_OPEN), token(&list, TOKEN_SQUARE_B_CLOSE)) {
+                        (Some(open), Some(close)) => (token_range(&open), token_range(&close)),
+                        _ => return Err(unsupported(&list, content))
+                    };
+
+                    insert_into_empty(content, open, close, &values);
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Remove the given Nix expressions from the list at the given path, failing if one of
+    /// them is not in the list.
+    pub fn list_remove<S: AsRef<str>>(&mut self, path: &Path, values: &[S]) -> Result<(), Error> {
+        self.edit(|root, content, options| {
+            let (_, items) = find_list(root, path, content, options)?;
+            let mut ranges = Vec::new();
+
+            for value in values {
+                match find_item(&items, content, value.as_ref()) {
+                    Some(i) => ranges.push(removal_range(content, span_range(&items[i]))),
+                    None => return Err(Error::NotFound(format!("Value '{}' is not in the list.", value.as_ref())))
+                }
+            }
+
+            ranges.sort_by_key(|range| range.start);
+            ranges.dedup();
+
+            for range in ranges.into_iter().rev() {
+                content.replace_range(range, "");
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Return whether the list at the given path contains the given Nix expression.
+    pub fn list_contains(&self, path: &Path, value: &str) -> Result<bool, Error> {
+        let (_, items) = find_list(&self.ast.node(), path, &self.source, &self.options)?;
+
+        Ok(find_item(&items, &self.source, value).is_some())
+    }
+
+    /// Return the values matching the given path, with one match per definition for values
+    /// defined several times.
+    fn definitions(&self, path: &Path) -> Result<Vec<Match>, Error> {
+        let (root, content) = (&self.ast.node(), &self.source);
+        let mut definitions = Vec::new();
+
+        for m in find_all(root, path, content, &self.options)? {
+            // Values defined several times are ambiguous, unless they are attribute sets, in
+            // which case we display a combined view of their entries like we do for values
+            // split across several entries (such as `a.b = 1;` and `a.c = 2;`)
+            let is_set = |(_, node): &(_, SyntaxNode)| node.kind() == NODE_ATTR_SET;
+
+            if m.entries.len() > 1 && !m.entries.iter().all(is_set) && is_ambiguous(&m) {
+                for entry in select_definitions(m.clone(), content, &self.options)?.entries {
+                    definitions.push(Match { path: m.path.clone(), entries: vec![entry] });
+                }
+            } else {
+                definitions.push(m);
+            }
+        }
+
+        Ok(definitions)
+    }
+
+    /// Set the value at the given path, rendered by the given function given the indentation
+    /// of the line it ends up on.
+    fn set_with(&mut self, path: &Path, render: &dyn Fn(&str) -> String) -> Result<(), Error> {
+        parse(&render(""), "value")?;
+
+        if find_matches(&self.ast.node(), path.segments(), &self.source, &self.options)?.is_empty() {
+            // We did not find a match, so we'll try to add the value ourselves
+            return self.insert_with(path, render)
+        }
+
+        self.edit(|root, content, options| {
+            // Replace every matched value, ignoring the ones nested in other matches
+            let mut ranges = Vec::new();
+
+            for m in find_matches(root, path.segments(), content, options)? {
+                for (key, node) in select_definitions(m.clone(), content, options)?.entries {
+                    if !key.is_empty() {
+                        return Err(Error::InvalidValue(format!("Path '{}' is defined across several entries, and cannot be replaced.", m.path)))
+                    }
+
+                    if is_inherited(&node) {
+                        return Err(Error::InvalidValue(format!("Path '{}' is inherited, and cannot be replaced.", m.path)))
+                    }
+
+                    ranges.push(span_range(&node));
+                }
+            }
+
+            ranges.sort_by_key(|range| (range.start, Reverse(range.end)));
+            ranges.dedup_by(|inner, outer| inner.end <= outer.end);
+
+            for range in ranges.into_iter().rev() {
+                let value = render(leading_whitespace(content, range.start));
+
+                content.replace_range(range, &value);
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Insert the value at the given path, rendered by the given function given the
+    /// indentation of the line it ends up on, next to the deepest prefix of the path that
+    /// already exists.
+    fn insert_with(&mut self, path: &Path, render: &dyn Fn(&str) -> String) -> Result<(), Error> {
+        let parts = path::names(path.segments())
+            .ok_or_else(|| no_match(&self.ast.node(), path, &self.source, &self.options))?;
+
+        self.edit(|root, content, options| {
+            let (set, prefix_len) = find_insertion_point(root, &parts, content, options)?;
+
+            insert_entry(&set, &parts[prefix_len..], render, content)
+        })
+    }
+
+    /// Apply the given change to a copy of the source, and replace the document by the
+    /// result if it is still valid.
+    fn edit<F>(&mut self, change: F) -> Result<(), Error>
+        where F: FnOnce(&SyntaxNode, &mut String, &Options) -> Result<(), Error>
+    {
+        let mut source = self.source.clone();
+
+        change(&self.ast.node(), &mut source, &self.options)?;
+
+        // Make sure we did not break anything
+        self.ast = parse(&source, "resulting file")?;
+        self.source = source;
+
+        Ok(())
+    }
+}
+
+/// Return the list at the given path and its items, failing if it is not a list.
+fn find_list(root: &SyntaxNode, path: &Path, content: &str, options: &Options) -> Result<(SyntaxNode, Vec<SyntaxNode>), Error> {
+    let mut matches = find_all(root, path, content, options)?;
+
+    if matches.len() > 1 {
+        return Err(Error::Ambiguous(format!("Path '{}' matches several values.", path)))
+    }
+
+    let node = match select_definitions(matches.remove(0), content, options)?.entries.as_slice() {
+        [(_, node)] if is_inherited(node) =>
+            return Err(Error::InvalidValue(format!("Path '{}' is inherited, and cannot be edited as a list.", path))),
+        [(key, node)] if key.is_empty() => node.clone(),
+        [_] => return Err(Error::InvalidValue(format!("Value at path '{}' is not a list.", path))),
+        _ => return Err(Error::Ambiguous(format!("Path '{}' matches several values.", path)))
+    };
+    let list = find_expr(&node, NODE_LIST)
+        .ok_or_else(|| Error::InvalidValue(format!("Value at path '{}' is not a list.", path)))?;
+    let items = list.children().collect();
+
+    Ok((list, items))
+}
+
+/// Return whether the given value is a name inherited from the scope or from another set.
+fn is_inherited(node: &SyntaxNode) -> bool {
+    node.parent().is_some_and(|parent| parent.kind() == NODE_INHERIT)
+}
+
+/// Return the index of the given item among the given items of a list, if any.
+fn find_item(items: &[SyntaxNode], content: &str, value: &str) -> Option<usize> {
+    items.iter().position(|item| content[span_range(item)] == *value.trim())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::*;
+    use Selection;
+
+    #[test]
+    fn test_document() {
+        let path = |path: &str| Path::parse(path).unwrap();
+        let mut document = Document::parse("{\n  a = 1;\n  b = [ 1 ];\n}".to_string()).unwrap();
+
+        assert_eq!(document.get(&path("a")), Ok(Value { path: "a".to_string(), source: "1".to_string() }));
+        assert_eq!(document.get(&path("c")), Err(Error::NotFound("No value matches path 'c'.".to_string())));
+        assert_eq!(document.get(&path("*")), Err(Error::Ambiguous("Path '*' matches several values.".to_string())));
+
+        document.set(&path("a"), "2").unwrap();
+        document.insert(&path("c.d"), "true").unwrap();
+        document.list_add(&path("b"), &["2"], false, true).unwrap();
+
+        assert_eq!(document.source(), "{\n  a = 2;\n  b = [ 1 2 ];\n  c.d = true;\n}");
+        assert_eq!(document.insert(&path("a"), "3"), Err(Error::InvalidValue("Path 'a' is already defined.".to_string())));
+        assert_eq!(document.list_contains(&path("b"), "2"), Ok(true));
+
+        // Changes that would break the document are not applied
+        assert!(document.set(&path("a"), "{").is_err());
+        assert!(document.list_add(&path("b"), &["-1"], false, false).is_err());
+
+        document.delete(&path("c.d"), true).unwrap();
+
+        assert_eq!(document.into_source(), "{\n  a = 2;\n  b = [ 1 2 ];\n}");
+    }
+
+    /// Run every query on the values of the given source, and every change if `edit` is set,
+    /// which must not panic whether or not they succeed.
+    fn exercise(source: &str, deep: bool, edit: bool) {
+        let mut document = match Document::parse(source.to_string()) {
+            Ok(document) => document,
+            Err(_) => return
+        };
+
+        document.options.deep = deep;
+        document.options.selection = Selection::First;
+
+        let mut paths: Vec<_> = document.paths(None).unwrap_or_default().into_iter().map(|value| value.path).collect();
+
+        paths.extend(["*", "*.*", "a", "a.b.c", "imports[*]", "options.services.backup.paths.default", "a[\u{a0}*]"]
+                         .iter()
+                         .map(|path| path.to_string()));
+
+        for path in paths.iter().filter_map(|path| Path::parse(path).ok()) {
+            let _ = document.get_all(&path);
+            let _ = document.evaluate(&path);
+            let _ = document.locate(&path);
+            let _ = document.paths(Some(&path));
+            let _ = document.list_contains(&path, "1");
+
+            if !edit {
+                continue
+            }
+
+            let mut changed = Document::parse(source.to_string()).unwrap();
+
+            changed.options = document.options.clone();
+
+            let _ = changed.set(&path, "{ x = 1; }");
+
+            for json in &["{\"x\": [1, \"\\u00e9\"]}", "\"\\ud800\\u0041\"", "\"\\udc00\"", "\"\\ud800\""] {
+                if let Ok(json) = Json::parse(json) {
+                    let _ = changed.set_json(&path, &json);
+                }
+            }
+
+            let _ = changed.insert(&path, "1");
+            let _ = changed.list_add(&path, &["\"x\""], false, true);
+            let _ = changed.list_remove(&path, &["\"x\""]);
+            let _ = changed.delete(&path, true);
+        }
+    }
+
+    #[test]
+    fn test_corpus() {
+        let mut dir = PathBuf::from(file!());
+
+        dir.pop();
+        dir.pop();
+        dir.push("tests");
+        dir.push("corpus");
+
+        for entry in dir.read_dir().unwrap() {
+            let source = fs::read_to_string(entry.unwrap().path()).unwrap();
+            let lines: Vec<_> = source.lines().collect();
+
+            exercise(&source, false, true);
+            exercise(&source, true, true);
+            exercise(&source.replace("# ", "#\u{a0}é "), false, true);
+
+            // Truncated files, and files missing a line
+            for i in 0..lines.len() {
+                exercise(&lines[..i].join("\n"), false, false);
+                exercise(&[&lines[..i], &lines[i + 1..]].concat().join("\n"), true, false);
+            }
+        }
+
+        // Non-ASCII whitespace and arithmetic overflows
+        exercise("{\n\u{a0}\u{a0}a = [\n\u{a0}1\n];\n\u{a0}b = /* c\n\u{a0}*/ ''\n\u{a0}x'';\n}", false, true);
+        exercise("{ a = -(0 - 9223372036854775807 - 1); b = 9223372036854775807 + 1; c = 1.0e308 * 10; d = 1.0e999; }", false, true);
+
+        // Comments left open in paths are reported as errors
+        assert_eq!(Document::parse("{ a = http/*//x.y; }".to_string()).err().map(|err| err.kind()), Some("parse"));
+    }
+}
@@ -0,0 +1,84 @@
+//! Errors reported when querying or modifying a document.
+
+use std::error;
+use std::fmt;
+
+
+/// An error, holding a message meant to be displayed to the user.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// The given path is not a valid path.
+    InvalidPath(String),
+
+    /// No value matches the given path.
+    NotFound(String),
+
+    /// The given path matches several values or definitions, and choosing one of them would
+    /// be arbitrary.
+    Ambiguous(String),
+
+    /// A document or value is not a valid Nix expression, with the line and column (both
+    /// starting at 1) of the first error.
+    Parse { message: String, line: usize, column: usize },
+
+    /// A given value is invalid, or the value at the given path cannot be used for the
+    /// requested operation.
+    InvalidValue(String),
+
+    /// A value could not be evaluated.
+    Eval(String),
+
+    /// A file could not be read or written, or a command could not be run.
+    Io(String),
+
+    /// The document contains a construct that cannot be handled, such as one that the parser
+    /// does not represent as expected.
+    Unsupported(String)
+}
+
+impl Error {
+    /// Return the message of the error.
+    pub fn message(&self) -> &str {
+        match *self {
+            Error::InvalidPath(ref message) | Error::NotFound(ref message) | Error::Ambiguous(ref message) |
+            Error::Parse { ref message, .. } | Error::InvalidValue(ref message) | Error::Eval(ref message) |
+            Error::Io(ref message) | Error::Unsupported(ref message) => message
+        }
+    }
+
+    /// Return the kind of the error, such as `not-found`, for machine consumption.
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            Error::InvalidPath(_) => "invalid-path",
+            Error::NotFound(_) => "not-found",
+            Error::Ambiguous(_) => "ambiguous",
+            Error::Parse { .. } => "parse",
+            Error::InvalidValue(_) => "invalid-value",
+            Error::Eval(_) => "eval",
+            Error::Io(_) => "io",
+            Error::Unsupported(_) => "unsupported"
+        }
+    }
+
+    /// Return the same error, with its message changed by the given function.
+    pub fn map_message<F: FnOnce(String) -> String>(self, f: F) -> Error {
+        match self {
+            Error::InvalidPath(message) => Error::InvalidPath(f(message)),
+            Error::NotFound(message) => Error::NotFound(f(message)),
+            Error::Ambiguous(message) => Error::Ambiguous(f(message)),
+            Error::Parse { message, line, column } => Error::Parse { message: f(message), line, column },
+            Error::InvalidValue(message) => Error::InvalidValue(f(message)),
+            Error::Eval(message) => Error::Eval(f(message)),
+            Error::Io(message) => Error::Io(f(message)),
+            Error::Unsupported(message) => Error::Unsupported(f(message))
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl error::Error for Error {}
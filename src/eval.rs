@@ -0,0 +1,798 @@
+//! Static evaluation of Nix expressions that do not depend on anything outside of the file,
+//! such as literals, arithmetic, string interpolation, `let` bindings and `//` merges.
+
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+use rnix::{StrPart, SyntaxNode};
+use rnix::SyntaxKind::*;
+use rnix::types::{AttrSet, BinOp, BinOpKind, Str, TypedNode};
+use rnix::value::{Anchor, Value as NixValue};
+
+use json::Json;
+use path::{attribute_names, AttrName, Match};
+use super::{construct_name, ident_name, locate, span_range};
+
+
+/// Maximum number of values being evaluated at the same time, after which we assume that
+/// the evaluation does not terminate.
+const MAX_DEPTH: usize = 200;
+
+/// An evaluated value, whose elements and attributes are only evaluated when needed.
+#[derive(Clone)]
+enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Path(String),
+    List(Vec<Thunk>),
+    Set(Vec<(String, Thunk)>)
+}
+
+impl Value {
+    /// Return the name of the type of the value, as used in error messages.
+    fn type_name(&self) -> &'static str {
+        match *self {
+            Value::Null => "null",
+            Value::Bool(_) => "a boolean",
+            Value::Int(_) => "an integer",
+            Value::Float(_) => "a float",
+            Value::String(_) => "a string",
+            Value::Path(_) => "a path",
+            Value::List(_) => "a list",
+            Value::Set(_) => "an attribute set"
+        }
+    }
+}
+
+/// A value that has not been evaluated yet.
+#[derive(Clone)]
+enum Thunk {
+    /// An expression, alongside the scope it is evaluated in.
+    Expr(SyntaxNode, Scope),
+
+    /// An attribute set defined across several entries, such as `a.b = 1;` and
+    /// `a = { c = 2; };`, alongside the part of their key that comes after the set.
+    Entries(Vec<(Vec<String>, Thunk)>),
+
+    /// An attribute of a set, such as the ones inherited with `inherit (set) name;`.
+    Select(Box<Thunk>, String, SyntaxNode)
+}
+
+/// The variables visible from an expression, given as the innermost expression defining
+/// variables (`let`, `rec`, functions and `with`) and its own scope.
+#[derive(Clone)]
+struct Scope(Option<Rc<(SyntaxNode, Scope)>>);
+
+/// Evaluate the value matched by a path, failing with the reason why it cannot be
+/// evaluated statically if it depends on something unknown.
+pub fn evaluate(m: &Match, content: &str) -> Result<Json, String> {
+    let mut evaluator = Evaluator { content, depth: 0 };
+    let thunk = match m.node() {
+        Some(node) => evaluator.thunk(&node),
+        None => {
+            let mut entries = Vec::new();
+
+            for (key, node) in &m.entries {
+                let mut names = Vec::new();
+
+                for name in key {
+                    match *name {
+                        AttrName::Static(ref name) => names.push(name.clone()),
+                        AttrName::Dynamic(ref node) => return Err(format!("the attribute name '{}' is computed at runtime (at {})",
+                                                                          &content[span_range(node)], evaluator.location(span_range(node).start)))
+                    }
+                }
+
+                entries.push((names, evaluator.thunk(node)));
+            }
+
+            Thunk::Entries(entries)
+        }
+    };
+
+    evaluator.force_json(thunk)
+}
+
+struct Evaluator<'a> {
+    content: &'a str,
+    depth: usize
+}
+
+impl<'a> Evaluator<'a> {
+    /// Return the line and column of the given position, as displayed in errors.
+    fn location(&self, pos: usize) -> String {
+        let (line, column) = locate(self.content, pos);
+
+        format!("{}:{}", line, column)
+    }
+
+    /// Return an error with the given reason, pointing to the given node.
+    fn error<T>(&self, node: &SyntaxNode, reason: String) -> Result<T, String> {
+        Err(format!("{} (at {})", reason, self.location(span_range(node).start)))
+    }
+
+    /// Return the given child of the given node, failing if the node does not have it.
+    fn child(&self, node: &SyntaxNode, i: usize) -> Result<SyntaxNode, String> {
+        match node.children().nth(i) {
+            Some(child) => Ok(child),
+            None => self.error(node, format!("this {} is malformed", construct_name(node.kind())))
+        }
+    }
+
+    /// Return the thunk of the given value of the file, in the scope it is defined in.
+    fn thunk(&self, node: &SyntaxNode) -> Thunk {
+        // Attributes inherited from a set are not variables
+        if let Some(parent) = node.parent() {
+            if parent.kind() == NODE_INHERIT {
+                let from = parent.children()
+                                 .find(|child| child.kind() == NODE_INHERIT_FROM)
+                                 .and_then(|from| from.children().next());
+
+                if let (Some(from), Some(name)) = (from, ident_name(node)) {
+                    let scope = self.scope_of(&from);
+
+                    return Thunk::Select(Box::new(Thunk::Expr(from, scope)), name, node.clone())
+                }
+            }
+        }
+
+        Thunk::Expr(node.clone(), self.scope_of(node))
+    }
+
+    /// Return the scope of the given node of the file.
+    fn scope_of(&self, node: &SyntaxNode) -> Scope {
+        let ancestors: Vec<_> = node.ancestors().collect();
+        let mut scope = Scope(None);
+
+        for i in (1..ancestors.len()).rev() {
+            let (child, parent) = (&ancestors[i - 1], &ancestors[i]);
+            let is_body = parent.last_child().as_ref() == Some(child);
+
+            // Inherited variables are looked up in the enclosing scope, unless they are
+            // inherited from an expression
+            let is_inherited = child.kind() == NODE_INHERIT &&
+                               (i < 2 || ancestors[i - 2].kind() != NODE_INHERIT_FROM);
+
+            let defines_variables = match parent.kind() {
+                NODE_LET_IN => !is_inherited,
+                NODE_ATTR_SET => self.is_rec(parent) && !is_inherited,
+                NODE_LAMBDA => true,
+                NODE_WITH => is_body,
+                _ => false
+            };
+
+            if defines_variables {
+                scope = Scope(Some(Rc::new((parent.clone(), scope))));
+            }
+        }
+
+        scope
+    }
+
+    fn is_rec(&self, set: &SyntaxNode) -> bool {
+        AttrSet::cast(set.clone()).is_some_and(|set| set.recursive())
+    }
+
+    /// Return the thunk of the variable with the given name, used at the given node.
+    fn lookup(&mut self, name: &str, scope: &Scope, node: &SyntaxNode) -> Result<Thunk, String> {
+        let mut scope = scope.clone();
+        let mut with = None;
+
+        while let Some(rc) = scope.0.clone() {
+            let (ref definition, ref parent) = *rc;
+
+            match definition.kind() {
+                NODE_LAMBDA => if self.arguments(definition).iter().any(|arg| arg == name) {
+                    return self.error(node, format!("'{}' is an argument of a function, and is not known statically", name))
+                },
+
+                NODE_WITH => { with.get_or_insert(definition.clone()); },
+
+                _ => {
+                    let attributes = self.attributes(definition, &scope, parent)?;
+
+                    if let Some((_, thunk)) = attributes.into_iter().find(|(attr, _)| attr == name) {
+                        return Ok(thunk)
+                    }
+                }
+            }
+
+            scope = parent.clone();
+        }
+
+        match with {
+            Some(with) => {
+                let namespace = self.child(&with, 0)?;
+                let namespace = self.content[span_range(&namespace)].to_string();
+
+                self.error(node, format!("'{}' may be an attribute of '{}', which is not known statically", name, namespace))
+            },
+            None => self.error(node, format!("'{}' is not defined", name))
+        }
+    }
+
+    /// Return the names of the arguments of the given function.
+    fn arguments(&self, lambda: &SyntaxNode) -> Vec<String> {
+        let mut names = Vec::new();
+        let mut nodes: Vec<_> = lambda.children().take(1).collect();
+
+        while let Some(node) = nodes.pop() {
+            match node.kind() {
+                NODE_IDENT => names.extend(ident_name(&node)),
+                NODE_PATTERN | NODE_PAT_BIND => nodes.extend(node.children()),
+                NODE_PAT_ENTRY => nodes.extend(node.children().take(1)),
+                _ => ()
+            }
+        }
+
+        names
+    }
+
+    /// Return the attributes defined by the given set or `let` expression, where `scope`
+    /// is the scope of their values and `outer` the scope of inherited variables.
+    fn attributes(&mut self, node: &SyntaxNode, scope: &Scope, outer: &Scope) -> Result<Vec<(String, Thunk)>, String> {
+        let mut attributes = Vec::new();
+
+        for child in node.children() {
+            match child.kind() {
+                NODE_KEY_VALUE => {
+                    let (key_node, value) = (self.child(&child, 0)?, self.child(&child, 1)?);
+                    let mut key = Vec::new();
+
+                    for name in attribute_names(&key_node) {
+                        match name {
+                            AttrName::Static(name) => key.push(name),
+                            AttrName::Dynamic(name) => key.push(self.attribute_name(&name, scope)?)
+                        }
+                    }
+
+                    match key.split_first() {
+                        Some((name, rest)) => add(&mut attributes, name, rest, Thunk::Expr(value, scope.clone())),
+                        None => return self.error(&key_node, "this attribute name is not supported".to_string())
+                    }
+                },
+
+                NODE_INHERIT => {
+                    let from = child.children().find(|node| node.kind() == NODE_INHERIT_FROM);
+
+                    for ident in child.children() {
+                        if let Some(name) = ident_name(&ident) {
+                            let thunk = match from {
+                                Some(ref from) => Thunk::Select(Box::new(Thunk::Expr(self.child(from, 0)?, scope.clone())), name.clone(), ident),
+                                None => Thunk::Expr(ident, outer.clone())
+                            };
+
+                            add(&mut attributes, &name, &[], thunk);
+                        }
+                    }
+                },
+
+                _ => ()
+            }
+        }
+
+        Ok(attributes)
+    }
+
+    /// Evaluate the attribute name at the given node, such as `a`, `"a"` or `${a}`.
+    fn attribute_name(&mut self, node: &SyntaxNode, scope: &Scope) -> Result<String, String> {
+        match node.kind() {
+            NODE_IDENT => Ok(node.text().to_string()),
+            NODE_STRING | NODE_DYNAMIC => {
+                let expr = if node.kind() == NODE_DYNAMIC { self.child(node, 0)? } else { node.clone() };
+
+                match self.eval(&expr, scope)? {
+                    Value::String(name) => Ok(name),
+                    value => self.error(node, format!("attribute names must be strings, but found {}", value.type_name()))
+                }
+            },
+            NODE_SELECT => self.error(node, "nested attribute names are not supported here".to_string()),
+            _ => self.error(node, "this attribute name is not supported".to_string())
+        }
+    }
+
+    /// Evaluate the given thunk.
+    fn force(&mut self, thunk: Thunk) -> Result<Value, String> {
+        match thunk {
+            Thunk::Expr(node, scope) => {
+                if self.depth == MAX_DEPTH {
+                    return self.error(&node, "infinite recursion encountered".to_string())
+                }
+
+                self.depth += 1;
+
+                let result = self.eval(&node, &scope);
+
+                self.depth -= 1;
+                result
+            },
+
+            Thunk::Entries(entries) => {
+                let mut attributes = Vec::new();
+
+                for (key, thunk) in entries {
+                    match key.split_first() {
+                        Some((name, rest)) => add(&mut attributes, name, rest, thunk),
+                        None => {
+                            let node = self.node_of(&thunk);
+
+                            match self.force(thunk)? {
+                                Value::Set(entries) => for (name, thunk) in entries {
+                                    add(&mut attributes, &name, &[], thunk);
+                                },
+                                value => return self.error(&node, format!("cannot merge {} with an attribute set defined elsewhere",
+                                                                          value.type_name()))
+                            }
+                        }
+                    }
+                }
+
+                Ok(Value::Set(attributes))
+            },
+
+            Thunk::Select(set, name, node) => {
+                let set = self.force(*set)?;
+
+                self.select(set, &name, &node)
+            }
+        }
+    }
+
+    /// Return the node a thunk was created from, used to locate errors.
+    fn node_of(&self, thunk: &Thunk) -> SyntaxNode {
+        match *thunk {
+            Thunk::Expr(ref node, _) | Thunk::Select(_, _, ref node) => node.clone(),
+            Thunk::Entries(ref entries) => self.node_of(&entries[0].1)
+        }
+    }
+
+    /// Return the attribute with the given name in the given value.
+    fn select(&mut self, set: Value, name: &str, node: &SyntaxNode) -> Result<Value, String> {
+        match set {
+            Value::Set(attributes) => match attributes.into_iter().find(|(attr, _)| attr == name) {
+                Some((_, thunk)) => self.force(thunk),
+                None => self.error(node, format!("attribute '{}' is missing", name))
+            },
+            value => self.error(node, format!("cannot select attribute '{}' of {}", name, value.type_name()))
+        }
+    }
+
+    /// Evaluate the expression at the given node, in the given scope.
+    fn eval(&mut self, node: &SyntaxNode, scope: &Scope) -> Result<Value, String> {
+        match node.kind() {
+            NODE_LITERAL => {
+                let token = match node.first_token() {
+                    Some(token) => token,
+                    None => return self.error(node, "this literal is malformed".to_string())
+                };
+
+                match NixValue::from_token(token.kind(), token.text()) {
+                    Ok(NixValue::Integer(value)) => Ok(Value::Int(value)),
+                    Ok(NixValue::Float(value)) if value.is_finite() => Ok(Value::Float(value)),
+                    Ok(NixValue::Float(_)) => self.error(node, format!("float '{}' is too large", token.text())),
+                    Ok(NixValue::String(uri)) => Ok(Value::String(uri)),
+                    Ok(NixValue::Path(Anchor::Absolute, path)) |
+                    Ok(NixValue::Path(Anchor::Relative, path)) => Ok(Value::Path(path)),
+                    Ok(NixValue::Path(_, _)) =>
+                        self.error(node, "paths from the search path or the home directory are not known statically".to_string()),
+                    Err(_) => self.error(node, format!("integer '{}' is too large", token.text()))
+                }
+            },
+
+            NODE_PAREN => self.eval(&self.child(node, 0)?, scope),
+
+            NODE_STRING => {
+                let parts = Str::cast(node.clone()).map(|string| string.parts()).unwrap_or_default();
+                let mut result = String::new();
+
+                for part in parts {
+                    match part {
+                        StrPart::Literal(literal) => result.push_str(&literal),
+                        StrPart::Ast(interpolation) => {
+                            let expr = self.child(&interpolation, 0)?;
+
+                            match self.eval(&expr, scope)? {
+                                Value::String(value) | Value::Path(value) => result.push_str(&value),
+                                value => return self.error(&expr, format!("cannot coerce {} to a string", value.type_name()))
+                            }
+                        }
+                    }
+                }
+
+                Ok(Value::String(result))
+            },
+
+            NODE_LIST => Ok(Value::List(node.children().map(|item| Thunk::Expr(item, scope.clone())).collect())),
+
+            NODE_ATTR_SET => if self.is_rec(node) {
+                let inner = Scope(Some(Rc::new((node.clone(), scope.clone()))));
+
+                self.attributes(node, &inner, scope).map(Value::Set)
+            } else {
+                self.attributes(node, scope, scope).map(Value::Set)
+            },
+
+            NODE_LET_IN => {
+                let inner = Scope(Some(Rc::new((node.clone(), scope.clone()))));
+
+                match node.last_child() {
+                    Some(body) => self.eval(&body, &inner),
+                    None => self.error(node, "this let expression is malformed".to_string())
+                }
+            },
+
+            // Unlike other variables, these are parsed as identifiers
+            NODE_IDENT => match node.text().to_string().as_str() {
+                "null" => Ok(Value::Null),
+                "true" => Ok(Value::Bool(true)),
+                "false" => Ok(Value::Bool(false)),
+                name => {
+                    let thunk = self.lookup(name, scope, node)?;
+
+                    self.force(thunk)
+                }
+            },
+
+            NODE_SELECT => {
+                let attr = self.child(node, 1)?;
+                let set = self.eval(&self.child(node, 0)?, scope)?;
+                let name = self.attribute_name(&attr, scope)?;
+
+                self.select(set, &name, &attr)
+            },
+
+            NODE_OR_DEFAULT => {
+                let (select, default) = (self.child(node, 0)?, self.child(node, 1)?);
+                let attr = self.child(&select, 1)?;
+                let set = self.eval(&self.child(&select, 0)?, scope)?;
+                let name = self.attribute_name(&attr, scope)?;
+
+                match set {
+                    Value::Set(ref attributes) if !attributes.iter().any(|(attr, _)| *attr == name) =>
+                        self.eval(&default, scope),
+                    Value::Set(_) => self.select(set, &name, &attr),
+                    _ => self.eval(&default, scope)
+                }
+            },
+
+            NODE_IF_ELSE => {
+                let condition = self.child(node, 0)?;
+
+                match self.eval(&condition, scope)? {
+                    Value::Bool(true) => self.eval(&self.child(node, 1)?, scope),
+                    Value::Bool(false) => self.eval(&self.child(node, 2)?, scope),
+                    value => self.error(&condition, format!("conditions must be booleans, but found {}", value.type_name()))
+                }
+            },
+
+            NODE_UNARY_OP => {
+                let operator = match node.first_token() {
+                    Some(operator) => operator,
+                    None => return self.error(node, "this unary operation is malformed".to_string())
+                };
+                let operand = self.eval(&self.child(node, 0)?, scope)?;
+
+                match (operator.kind(), operand) {
+                    (TOKEN_SUB, Value::Int(value)) => match value.checked_neg() {
+                        Some(value) => Ok(Value::Int(value)),
+                        None => self.error(node, "integer overflow".to_string())
+                    },
+                    (TOKEN_SUB, Value::Float(value)) => Ok(Value::Float(-value)),
+                    (TOKEN_INVERT, Value::Bool(value)) => Ok(Value::Bool(!value)),
+                    (_, value) => self.error(node, format!("cannot apply '{}' to {}", operator.text(), value.type_name()))
+                }
+            },
+
+            NODE_BIN_OP => match BinOp::cast(node.clone()).and_then(|operation| operation.operator()) {
+                Some(operator) => {
+                    let (left, right) = (self.child(node, 0)?, self.child(node, 1)?);
+
+                    self.operation(node, operator, &left, &right, scope)
+                },
+                None => self.error(node, "this operation is not supported".to_string())
+            },
+
+            NODE_APPLY => self.error(node, "function calls are not supported".to_string()),
+            NODE_LAMBDA => self.error(node, "functions are not supported".to_string()),
+            NODE_WITH => self.error(node, "`with` expressions are not supported".to_string()),
+            NODE_ASSERT => self.error(node, "assertions are not supported".to_string()),
+            _ => self.error(node, "this expression is not supported".to_string())
+        }
+    }
+
+    /// Evaluate the binary operation with the given operator and operands.
+    fn operation(&mut self, node: &SyntaxNode, operator: BinOpKind, left: &SyntaxNode, right: &SyntaxNode, scope: &Scope) -> Result<Value, String> {
+        // Boolean operators only evaluate their right operand if needed
+        match operator {
+            BinOpKind::And | BinOpKind::Or | BinOpKind::Implication => {
+                let left = self.boolean(left, scope)?;
+
+                return match (operator, left) {
+                    (BinOpKind::And, false) => Ok(Value::Bool(false)),
+                    (BinOpKind::Or, true) => Ok(Value::Bool(true)),
+                    (BinOpKind::Implication, false) => Ok(Value::Bool(true)),
+                    _ => self.boolean(right, scope).map(Value::Bool)
+                }
+            },
+
+            BinOpKind::IsSet => {
+                let set = self.eval(left, scope)?;
+                let name = self.attribute_name(right, scope)?;
+
+                return match set {
+                    Value::Set(attributes) => Ok(Value::Bool(attributes.iter().any(|(attr, _)| *attr == name))),
+                    _ => Ok(Value::Bool(false))
+                }
+            },
+
+            _ => ()
+        }
+
+        let left = self.eval(left, scope)?;
+        let right = self.eval(right, scope)?;
+        let mismatch = |left: &Value, right: &Value| {
+            format!("cannot apply '{:?}' to {} and {}", operator, left.type_name(), right.type_name())
+        };
+
+        let result = match (operator, left, right) {
+            (BinOpKind::Add, Value::Int(a), Value::Int(b)) => a.checked_add(b).map(Value::Int),
+            (BinOpKind::Sub, Value::Int(a), Value::Int(b)) => a.checked_sub(b).map(Value::Int),
+            (BinOpKind::Mul, Value::Int(a), Value::Int(b)) => a.checked_mul(b).map(Value::Int),
+            (BinOpKind::Div, Value::Int(_), Value::Int(0)) => return self.error(node, "division by zero".to_string()),
+            (BinOpKind::Div, Value::Int(a), Value::Int(b)) => a.checked_div(b).map(Value::Int),
+
+            (BinOpKind::Add, Value::String(a), Value::String(b)) |
+            (BinOpKind::Add, Value::String(a), Value::Path(b)) => Some(Value::String(a + &b)),
+            (BinOpKind::Add, Value::Path(a), Value::String(b)) |
+            (BinOpKind::Add, Value::Path(a), Value::Path(b)) => Some(Value::Path(a + &b)),
+
+            (BinOpKind::Concat, Value::List(mut a), Value::List(b)) => {
+                a.extend(b);
+
+                Some(Value::List(a))
+            },
+
+            (BinOpKind::Update, Value::Set(mut a), Value::Set(b)) => {
+                for (name, thunk) in b {
+                    match a.iter_mut().find(|&&mut (ref attr, _)| *attr == name) {
+                        Some(&mut (_, ref mut existing)) => *existing = thunk,
+                        None => a.push((name, thunk))
+                    }
+                }
+
+                Some(Value::Set(a))
+            },
+
+            (BinOpKind::Equal, left, right) => return self.equals(left, right).map(Value::Bool),
+            (BinOpKind::NotEqual, left, right) => return self.equals(left, right).map(|equal| Value::Bool(!equal)),
+
+            (BinOpKind::Less, left, right) | (BinOpKind::LessOrEq, left, right) |
+            (BinOpKind::More, left, right) | (BinOpKind::MoreOrEq, left, right) => {
+                let ordering = match (&left, &right) {
+                    (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+                    _ => match (number(&left), number(&right)) {
+                        (Some(a), Some(b)) => a.partial_cmp(&b),
+                        _ => return self.error(node, mismatch(&left, &right))
+                    }
+                };
+
+                ordering.map(|ordering| Value::Bool(match operator {
+                    BinOpKind::Less => ordering == Ordering::Less,
+                    BinOpKind::LessOrEq => ordering != Ordering::Greater,
+                    BinOpKind::More => ordering == Ordering::Greater,
+                    _ => ordering != Ordering::Less
+                }))
+            },
+
+            (_, left, right) => match (number(&left), number(&right)) {
+                // Arithmetic on floats, where integers are converted to floats
+                (Some(a), Some(b)) => match operator {
+                    BinOpKind::Add => Some(Value::Float(a + b)),
+                    BinOpKind::Sub => Some(Value::Float(a - b)),
+                    BinOpKind::Mul => Some(Value::Float(a * b)),
+                    BinOpKind::Div if b == 0.0 => return self.error(node, "division by zero".to_string()),
+                    BinOpKind::Div => Some(Value::Float(a / b)),
+                    _ => return self.error(node, mismatch(&left, &right))
+                },
+                _ => return self.error(node, mismatch(&left, &right))
+            }
+        };
+
+        match result {
+            Some(Value::Float(value)) if !value.is_finite() => self.error(node, "float overflow".to_string()),
+            Some(value) => Ok(value),
+            None => self.error(node, "integer overflow".to_string())
+        }
+    }
+
+    /// Evaluate the given node, which must be a boolean.
+    fn boolean(&mut self, node: &SyntaxNode, scope: &Scope) -> Result<bool, String> {
+        match self.eval(node, scope)? {
+            Value::Bool(value) => Ok(value),
+            value => self.error(node, format!("expected a boolean, but found {}", value.type_name()))
+        }
+    }
+
+    /// Return whether both values are equal, evaluating them deeply.
+    fn equals(&mut self, left: Value, right: Value) -> Result<bool, String> {
+        match (left, right) {
+            (Value::List(a), Value::List(b)) => {
+                if a.len() != b.len() {
+                    return Ok(false)
+                }
+
+                for (a, b) in a.into_iter().zip(b) {
+                    let (a, b) = (self.force(a)?, self.force(b)?);
+
+                    if !self.equals(a, b)? {
+                        return Ok(false)
+                    }
+                }
+
+                Ok(true)
+            },
+
+            (Value::Set(a), Value::Set(mut b)) => {
+                if a.len() != b.len() {
+                    return Ok(false)
+                }
+
+                for (name, a) in a {
+                    let b = match b.iter().position(|(attr, _)| *attr == name) {
+                        Some(i) => b.swap_remove(i).1,
+                        None => return Ok(false)
+                    };
+                    let (a, b) = (self.force(a)?, self.force(b)?);
+
+                    if !self.equals(a, b)? {
+                        return Ok(false)
+                    }
+                }
+
+                Ok(true)
+            },
+
+            (Value::Null, Value::Null) => Ok(true),
+            (Value::Bool(a), Value::Bool(b)) => Ok(a == b),
+            (Value::String(a), Value::String(b)) | (Value::Path(a), Value::Path(b)) => Ok(a == b),
+            (left, right) => match (number(&left), number(&right)) {
+                (Some(a), Some(b)) => Ok(a == b),
+                _ => Ok(false)
+            }
+        }
+    }
+
+    /// Evaluate the given thunk deeply, and convert it to JSON.
+    fn force_json(&mut self, thunk: Thunk) -> Result<Json, String> {
+        Ok(match self.force(thunk)? {
+            Value::Null => Json::Null,
+            Value::Bool(value) => Json::Bool(value),
+            Value::Int(value) => Json::Number(value.to_string()),
+            Value::Float(value) => Json::Number(format!("{:?}", value)),
+            Value::String(value) => Json::String(value),
+            Value::Path(path) => Json::Nix(path),
+            Value::List(items) => {
+                let mut values = Vec::new();
+
+                for item in items {
+                    values.push(self.force_json(item)?);
+                }
+
+                Json::Array(values)
+            },
+            Value::Set(attributes) => {
+                let mut entries = Vec::new();
+
+                for (name, thunk) in attributes {
+                    entries.push((name, self.force_json(thunk)?));
+                }
+
+                Json::Object(entries)
+            }
+        })
+    }
+}
+
+/// Add the attribute with the given name to the given attributes, merging it with an
+/// existing attribute with the same name if there is one.
+fn add(attributes: &mut Vec<(String, Thunk)>, name: &str, rest: &[String], thunk: Thunk) {
+    let entry = (rest.to_vec(), thunk);
+
+    match attributes.iter_mut().find(|&&mut (ref attr, _)| attr == name) {
+        Some(&mut (_, ref mut existing)) => match *existing {
+            Thunk::Entries(ref mut entries) => entries.push(entry),
+            ref mut existing => *existing = Thunk::Entries(vec![(Vec::new(), existing.clone()), entry])
+        },
+
+        None if rest.is_empty() => attributes.push((name.to_string(), entry.1)),
+        None => attributes.push((name.to_string(), Thunk::Entries(vec![entry])))
+    }
+}
+
+/// Return the given value as a float, if it is a number.
+fn number(value: &Value) -> Option<f64> {
+    match *value {
+        Value::Int(value) => Some(value as f64),
+        Value::Float(value) => Some(value),
+        _ => None
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use path::{parse_path, resolve};
+
+    fn eval(content: &str, path: &str) -> Result<String, String> {
+        let ast = rnix::parse(content);
+        let matches = resolve(&ast.node(), &parse_path(path).unwrap(), content).unwrap();
+
+        evaluate(&matches[0], content).map(|json| json.to_string())
+    }
+
+    fn assert_eval_eq(content: &str, path: &str, expected: &str) {
+        assert_eq!(eval(content, path), Ok(expected.to_string()))
+    }
+
+    #[test]
+    fn test_literals() {
+        assert_eval_eq("{ a = [ null true 1 (-2.5) \"s\" ./p ]; }", "a", r#"[null,true,1,-2.5,"s",{"$nix":"./p"}]"#);
+        assert_eval_eq("{ a = 1 + 2 * 3 - 4 / 2; }", "a", "5");
+        assert_eval_eq("{ a = 1 + 0.5; }", "a", "1.5");
+        assert_eval_eq("{ a = 7 > 3 && !(1 == 2) || b; }", "a", "true");
+        assert_eval_eq("{ a = \"a\" + \"b\"; }", "a", r#""ab""#);
+        assert_eval_eq("{ a = [ 1 ] ++ [ 2 ]; }", "a", "[1,2]");
+        assert_eval_eq("{ a = { b = 1; c = 2; } // { c = 3; }; }", "a", r#"{"b":1,"c":3}"#);
+        assert_eval_eq("{ a = if 1 < 2 then \"yes\" else throw \"no\"; }", "a", r#""yes""#);
+        assert_eval_eq("{ a = { b = 1; } ? b; }", "a", "true");
+        assert_eval_eq("{ a = { b = 1; }.c or 2; }", "a", "2");
+    }
+
+    #[test]
+    fn test_variables() {
+        let nix = r#"{ config, ... }:
+
+          let
+            port = 8000 + 80;
+            host = "localhost";
+            inherit (settings) user;
+            settings = { user = "alice"; };
+          in
+
+          with config; {
+            url = "http://${host}:${toString port}";
+            address = "${host}";
+            endpoint = rec { inherit port; path = "/api"; full = "${address}${path}"; address = host; };
+            owner = user;
+            nested.a = port;
+            nested.b = { c = host; };
+            enabled = config.enable;
+            other = foo;
+            loop = let a = a; in a;
+          }
+        "#;
+
+        assert_eval_eq(nix, "address", r#""localhost""#);
+        assert_eval_eq(nix, "endpoint", r#"{"port":8080,"path":"/api","full":"localhost/api","address":"localhost"}"#);
+        assert_eval_eq(nix, "owner", r#""alice""#);
+        assert_eval_eq(nix, "nested", r#"{"a":8080,"b":{"c":"localhost"}}"#);
+        assert_eval_eq(nix, "let.user", r#""alice""#);
+
+        assert_eq!(eval(nix, "url"), Err("function calls are not supported (at 11:37)".to_string()));
+        assert_eq!(eval(nix, "enabled"), Err("'config' is an argument of a function, and is not known statically (at 17:23)".to_string()));
+        assert_eq!(eval(nix, "other"), Err("'foo' may be an attribute of 'config', which is not known statically (at 18:21)".to_string()));
+        assert_eq!(eval(nix, "loop"), Err("infinite recursion encountered (at 19:28)".to_string()));
+        assert_eq!(eval("{ a = 1 + \"b\"; }", "a"), Err("cannot apply 'Add' to an integer and a string (at 1:7)".to_string()));
+        assert_eq!(eval("{ a = 1 / 0; }", "a"), Err("division by zero (at 1:7)".to_string()));
+        assert_eq!(eval("{ a = -(0 - 9223372036854775807 - 1); }", "a"), Err("integer overflow (at 1:7)".to_string()));
+        assert_eq!(eval("{ a = 9223372036854775807 * 2; }", "a"), Err("integer overflow (at 1:7)".to_string()));
+        assert_eq!(eval("{ a = 1.0e308 * 10; }", "a"), Err("float overflow (at 1:7)".to_string()));
+        assert_eq!(eval("{ a = 1.0e999; }", "a"), Err("float '1.0e999' is too large (at 1:7)".to_string()));
+    }
+}
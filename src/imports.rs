@@ -0,0 +1,122 @@
+//! Discovery of the files imported by a configuration through its `imports` list.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rnix::SyntaxNode;
+use rnix::value::{Anchor, Value as NixValue};
+use rnix::SyntaxKind::*;
+
+use error::Error;
+use path::{self, resolve, Segment};
+use super::{find_matches, parse, Options};
+
+
+/// Return the given file followed by the files it imports, recursively and in the order of
+/// their `imports` lists, alongside their content. Files imported several times are only
+/// returned once.
+pub fn load_imports(path: &Path, content: String) -> Result<Vec<(PathBuf, String)>, Error> {
+    let mut files = Vec::new();
+
+    load(path.to_path_buf(), content, &mut files)?;
+
+    Ok(files)
+}
+
+/// Return the index of the file defining the given path among the given files, or of the
+/// file defining the longest prefix of the path if it should be inserted. The first file is
+/// used if no file defines it.
+pub fn defining_file(files: &[(PathBuf, String)], path: &path::Path, insert: bool, options: &Options) -> Result<usize, Error> {
+    let mut asts = Vec::new();
+
+    for (file, content) in files {
+        asts.push(parse(content, &format!("file '{}'", file.display()))?);
+    }
+
+    let defining = |segments: &[Segment]| -> Result<Vec<usize>, Error> {
+        let mut defining = Vec::new();
+
+        for i in 0..files.len() {
+            if !find_matches(&asts[i].node(), segments, &files[i].1, options)?.is_empty() {
+                defining.push(i);
+            }
+        }
+
+        Ok(defining)
+    };
+    let segments = path.segments();
+
+    match defining(segments)?.as_slice() {
+        &[] => (),
+        &[i] => return Ok(i),
+        indices => {
+            let names: Vec<_> = indices.iter().map(|&i| files[i].0.display().to_string()).collect();
+
+            return Err(Error::Ambiguous(format!("Path '{}' is defined in several files ({}).", path, names.join(", "))))
+        }
+    }
+
+    // Values that do not exist yet are inserted next to their deepest prefix that does
+    if insert && path::names(segments).is_some() {
+        for prefix_len in (1..segments.len()).rev() {
+            if let Some(&i) = defining(&segments[..prefix_len])?.first() {
+                return Ok(i)
+            }
+        }
+    }
+
+    Ok(0)
+}
+
+fn load(path: PathBuf, content: String, files: &mut Vec<(PathBuf, String)>) -> Result<(), Error> {
+    let what = if files.is_empty() { "input file".to_string() } else { format!("imported file '{}'", path.display()) };
+    let imports = import_paths(&parse(&content, &what)?.node(), &path, &content)?;
+
+    files.push((path, content));
+
+    for import in imports {
+        if files.iter().any(|(path, _)| is_same_file(path, &import)) {
+            continue
+        }
+
+        let content = fs::read_to_string(&import)
+            .map_err(|err| Error::Io(format!("Unable to read imported file '{}': {}.", import.display(), err)))?;
+
+        load(import, content, files)?;
+    }
+
+    Ok(())
+}
+
+/// Return the files imported by the given file, ignoring imports that are not path
+/// literals, such as `<nixpkgs/nixos/modules/...>` or function calls.
+fn import_paths(root: &SyntaxNode, file: &Path, content: &str) -> Result<Vec<PathBuf>, Error> {
+    let dir = file.parent().unwrap_or_else(|| Path::new(""));
+    let segments = [Segment::Name("imports".to_string()), Segment::AnyItem];
+
+    Ok(resolve(root, &segments, content)?
+        .iter()
+        .filter_map(|m| m.node())
+        .filter(|node| node.kind() == NODE_LITERAL)
+        .filter_map(|node| node.first_token())
+        .filter_map(|token| match NixValue::from_token(token.kind(), token.text()) {
+            Ok(NixValue::Path(Anchor::Relative, path)) => Some(dir.join(path)),
+            Ok(NixValue::Path(Anchor::Absolute, path)) => Some(PathBuf::from(path)),
+            _ => None
+        })
+        .map(|path| {
+            // Remove the `.` components, and import directories through their `default.nix`
+            let path: PathBuf = path.components().collect();
+
+            if path.is_dir() { path.join("default.nix") } else { path }
+        })
+        .collect())
+}
+
+/// Return whether both paths refer to the same file.
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b
+    }
+}
@@ -0,0 +1,114 @@
+//! Evaluation of values with `nix-instantiate`, for expressions that cannot be evaluated
+//! statically, such as `lib.mkIf` or `pkgs.foo`.
+
+use std::io::ErrorKind;
+use std::path::Path;
+use std::process::Command;
+
+use rnix::{SyntaxNode, SyntaxToken};
+use rnix::SyntaxKind::*;
+
+use error::Error;
+use json::Json;
+use super::{ident_name, span_range, token, token_range};
+
+
+/// Evaluate the given expression with the given `nix-instantiate` command, where `node` is
+/// the node of the file that the expression is evaluated at, and `dir` the directory relative
+/// paths are resolved from.
+pub fn nix_eval(node: &SyntaxNode, expr: &str, content: &str, command: &str, dir: &Path) -> Result<Json, Error> {
+    let expr = wrap(node, expr, content);
+    let mut process = Command::new(command);
+
+    process.args(["--eval", "--json", "--strict", "-E", &expr]);
+
+    if !dir.as_os_str().is_empty() {
+        process.current_dir(dir);
+    }
+
+    let output = process.output().map_err(|err| match err.kind() {
+        ErrorKind::NotFound => Error::Io(format!("Unable to find '{}'; make sure that Nix is installed.", command)),
+        _ => Error::Io(format!("Unable to run '{}': {}.", command, err))
+    })?;
+
+    if !output.status.success() {
+        return Err(Error::Eval(format!("Evaluation with '{}' failed:\n{}", command, String::from_utf8_lossy(&output.stderr).trim_end())))
+    }
+
+    Json::parse(String::from_utf8_lossy(&output.stdout).trim())
+}
+
+/// Wrap the given expression in the `let` bindings, `with` expressions and functions that
+/// surround the given node, so that it can be evaluated on its own. The arguments of the
+/// functions are replaced by stubs.
+fn wrap(node: &SyntaxNode, expr: &str, content: &str) -> String {
+    let mut expr = expr.to_string();
+    let mut child = node.clone();
+
+    while let Some(parent) = child.parent() {
+        let children: Vec<_> = parent.children().collect();
+        let is_body = children.last() == Some(&child);
+
+        match parent.kind() {
+            NODE_LET_IN => if let (Some(start), Some(end)) = (token(&parent, TOKEN_LET), token(&parent, TOKEN_IN)) {
+                expr = format!("let {} in {}", between(content, &start, &end), expr);
+            },
+            // The entries of recursive sets are visible from their values, like bindings
+            NODE_ATTR_SET => if let (Some(_), Some(start), Some(end)) =
+                    (token(&parent, TOKEN_REC), token(&parent, TOKEN_CURLY_B_OPEN), token(&parent, TOKEN_CURLY_B_CLOSE)) {
+                expr = format!("let {} in {}", between(content, &start, &end), expr);
+            },
+
+            NODE_WITH if is_body => {
+                expr = format!("with {}; {}", &content[span_range(&children[0])], expr);
+            },
+
+            NODE_LAMBDA => {
+                expr = format!("({}: {}) {}", &content[span_range(&children[0])], expr, stub_arguments(&children[0]));
+            },
+
+            _ => ()
+        }
+
+        child = parent;
+    }
+
+    expr
+}
+
+/// Return the source between the given tokens.
+fn between<'a>(content: &'a str, start: &SyntaxToken, end: &SyntaxToken) -> &'a str {
+    content[token_range(start).end..token_range(end).start].trim()
+}
+
+/// Return the stub passed to a function with the given argument, which provides the
+/// arguments of the pattern that have no default value.
+fn stub_arguments(argument: &SyntaxNode) -> String {
+    if argument.kind() != NODE_PATTERN {
+        return "{ }".to_string()
+    }
+
+    let stubs: Vec<_> = argument.children()
+        .filter(|node| node.kind() == NODE_PAT_ENTRY && token(node, TOKEN_QUESTION).is_none())
+        .filter_map(|entry| entry.first_child())
+        .filter_map(|name| ident_name(&name))
+        .map(|name| format!("{} = {};", name, stub(&name)))
+        .collect();
+
+    if stubs.is_empty() {
+        "{ }".to_string()
+    } else {
+        format!("{{ {} }}", stubs.join(" "))
+    }
+}
+
+/// Return the stub of the function argument with the given name, using the usual arguments
+/// of NixOS modules.
+fn stub(name: &str) -> &'static str {
+    match name {
+        "pkgs" => "import <nixpkgs> { }",
+        "lib" => "(import <nixpkgs> { }).lib",
+        "modulesPath" => "<nixpkgs/nixos/modules>",
+        _ => "{ }"
+    }
+}
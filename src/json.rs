@@ -0,0 +1,618 @@
+//! Conversion of Nix expressions to JSON.
+
+use std::fmt;
+
+use rnix::SyntaxNode;
+use rnix::SyntaxKind::*;
+
+use error::Error;
+use super::{ident_name, parse, span_range, static_string};
+use path::{attribute_names, format_name, AttrName};
+
+
+/// A JSON value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+
+    /// A Nix expression that has no JSON equivalent, displayed as `{"$nix":"<source>"}`.
+    Nix(String)
+}
+
+impl Json {
+    /// Parse the given Nix expression, and convert it to JSON.
+    pub fn from_source(source: &str) -> Result<Json, Error> {
+        let ast = parse(source, "value")?;
+
+        Ok(match ast.node().first_child() {
+            Some(expr) => Json::from_node(&expr, source),
+            None => Json::Nix(source.trim().to_string())
+        })
+    }
+
+    /// Convert the given node to JSON, where `source` is the source of the whole tree.
+    pub fn from_node(node: &SyntaxNode, source: &str) -> Json {
+        let nix = || Json::Nix(source[span_range(node)].to_string());
+
+        match node.kind() {
+            NODE_LITERAL => match node.first_token() {
+                Some(ref token) if token.kind() == TOKEN_INTEGER => Json::Number(token.text().to_string()),
+                Some(ref token) if token.kind() == TOKEN_FLOAT => Json::Number(normalize_float(token.text())),
+
+                // Paths have no JSON equivalent
+                _ => nix()
+            },
+
+            NODE_IDENT => match node.text().to_string().as_str() {
+                "null" => Json::Null,
+                "true" => Json::Bool(true),
+                "false" => Json::Bool(false),
+                _ => nix()
+            },
+
+            NODE_STRING => match static_string(node) {
+                Some(content) => Json::String(content),
+                None => nix()
+            },
+
+            NODE_PAREN => match node.first_child() {
+                Some(expr) => Json::from_node(&expr, source),
+                None => nix()
+            },
+
+            NODE_UNARY_OP => {
+                // Negative numbers are represented as a negation of a positive number
+                let (operator, operand) = match (node.first_token(), node.first_child().and_then(|operand| operand.first_token())) {
+                    (Some(operator), Some(operand)) => (operator, operand),
+                    _ => return nix()
+                };
+
+                match (operator.kind(), operand.kind()) {
+                    (TOKEN_SUB, TOKEN_INTEGER) => Json::Number(format!("-{}", operand.text())),
+                    (TOKEN_SUB, TOKEN_FLOAT) => Json::Number(format!("-{}", normalize_float(operand.text()))),
+
+                    _ => nix()
+                }
+            },
+
+            NODE_LIST => Json::Array(node.children().map(|item| Json::from_node(&item, source)).collect()),
+
+            NODE_ATTR_SET => {
+                let mut entries = Vec::new();
+
+                for child in node.children() {
+                    match child.kind() {
+                        NODE_KEY_VALUE => {
+                            let (key, value) = match (child.first_child(), child.children().nth(1)) {
+                                (Some(key), Some(value)) => (key, value),
+                                _ => return nix()
+                            };
+
+                            // Keys that are computed at runtime cannot be converted
+                            let path: Option<Vec<_>> = attribute_names(&key)
+                                .into_iter()
+                                .map(|name| match name {
+                                    AttrName::Static(name) => Some(name),
+                                    AttrName::Dynamic(_) => None
+                                })
+                                .collect();
+                            let path = match path {
+                                Some(ref path) if !path.is_empty() => path,
+                                _ => return nix()
+                            };
+
+                            insert(&mut entries, path, Json::from_node(&value, source));
+                        },
+
+                        NODE_INHERIT => {
+                            let from = child.children()
+                                            .find(|node| node.kind() == NODE_INHERIT_FROM)
+                                            .and_then(|from| from.first_child());
+
+                            for name in child.children().filter_map(|inherited| ident_name(&inherited)) {
+                                let value = match from {
+                                    Some(ref from) if from.kind() == NODE_IDENT => format!("{}.{}", &source[span_range(from)], name),
+                                    Some(ref from) => format!("({}).{}", &source[span_range(from)], name),
+                                    None => name.clone()
+                                };
+
+                                insert(&mut entries, &[name], Json::Nix(value));
+                            }
+                        },
+
+                        NODE_ERROR => return nix(),
+
+                        _ => ()
+                    }
+                }
+
+                Json::Object(entries)
+            },
+
+            _ => nix()
+        }
+    }
+}
+
+impl Json {
+    /// Parse the given JSON value.
+    pub fn parse(input: &str) -> Result<Json, Error> {
+        let mut parser = Parser { input, pos: 0 };
+        let value = parser.value().map_err(Error::InvalidValue)?;
+
+        parser.skip_whitespace();
+
+        if parser.pos != input.len() {
+            return Err(Error::InvalidValue(parser.error("end of input")))
+        }
+
+        Ok(value)
+    }
+
+    /// Render the value as a Nix expression, given the indentation of the line it starts
+    /// on and the indentation unit of the file.
+    pub fn to_nix(&self, indent: &str, unit: &str) -> String {
+        let inner = format!("{}{}", indent, unit);
+
+        match *self {
+            Json::Null => "null".to_string(),
+            Json::Bool(value) => value.to_string(),
+            Json::Number(ref value) => {
+                // Nix floats must have a decimal part before their exponent
+                match value.find(['e', 'E']) {
+                    Some(i) if !value.contains('.') => format!("{}.0{}", &value[..i], &value[i..]),
+                    _ => value.clone()
+                }
+            },
+            Json::String(ref value) => nix_string(value),
+            Json::Nix(ref source) => source.clone(),
+
+            Json::Array(ref values) if values.is_empty() => "[ ]".to_string(),
+            Json::Array(ref values) => {
+                let items: Vec<_> = values.iter().map(|value| {
+                    let item = value.to_nix(&inner, unit);
+
+                    // Function applications and negative numbers must be wrapped in
+                    // parentheses in lists
+                    match *value {
+                        Json::Number(_) | Json::Nix(_) if item.contains(char::is_whitespace) || item.starts_with('-') =>
+                            format!("({})", item),
+                        _ => item
+                    }
+                }).collect();
+
+                let is_inline = values.iter().all(|value| !matches!(*value, Json::Array(_) | Json::Object(_)));
+
+                if is_inline {
+                    format!("[ {} ]", items.join(" "))
+                } else {
+                    let items: String = items.iter().map(|item| format!("{}{}\n", inner, item)).collect();
+
+                    format!("[\n{}{}]", items, indent)
+                }
+            },
+
+            Json::Object(ref entries) if entries.is_empty() => "{ }".to_string(),
+            Json::Object(ref entries) => {
+                let entries: String = entries.iter()
+                                             .map(|(key, value)| {
+                                                 format!("{}{} = {};\n", inner, format_name(key), value.to_nix(&inner, unit))
+                                             })
+                                             .collect();
+
+                format!("{{\n{}{}}}", entries, indent)
+            }
+        }
+    }
+}
+
+/// Render the given string as a Nix string literal.
+pub fn nix_string(value: &str) -> String {
+    let mut result = String::with_capacity(value.len() + 2);
+
+    result.push('"');
+
+    for (i, ch) in value.char_indices() {
+        match ch {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            '$' if value[i + 1..].starts_with('{') => result.push_str("\\$"),
+            ch => result.push(ch)
+        }
+    }
+
+    result.push('"');
+    result
+}
+
+/// A parser of JSON values.
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, expected: &str) -> String {
+        format!("Invalid JSON value: expected {} at position {}.", expected, self.pos)
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.input[self.pos..];
+
+        self.pos += rest.len() - rest.trim_start_matches([' ', '\t', '\n', '\r']).len();
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        if self.input[self.pos..].starts_with(s) {
+            self.pos += s.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, s: &str) -> Result<(), String> {
+        if self.eat(s) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("'{}'", s)))
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+
+        match self.peek() {
+            Some('n') => self.expect("null").map(|_| Json::Null),
+            Some('t') => self.expect("true").map(|_| Json::Bool(true)),
+            Some('f') => self.expect("false").map(|_| Json::Bool(false)),
+            Some('"') => self.string().map(Json::String),
+            Some('[') => self.array(),
+            Some('{') => self.object(),
+            Some(ch) if ch == '-' || ch.is_ascii_digit() => self.number(),
+
+            _ => Err(self.error("a value"))
+        }
+    }
+
+    fn digits(&mut self) -> Result<(), String> {
+        let rest = &self.input[self.pos..];
+        let len = rest.len() - rest.trim_start_matches(|ch: char| ch.is_ascii_digit()).len();
+
+        if len == 0 {
+            return Err(self.error("a digit"))
+        }
+
+        self.pos += len;
+
+        Ok(())
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.pos;
+
+        self.eat("-");
+
+        if !self.eat("0") {
+            self.digits()?;
+        }
+
+        if self.eat(".") {
+            self.digits()?;
+        }
+
+        if self.eat("e") || self.eat("E") {
+            if !self.eat("+") {
+                self.eat("-");
+            }
+
+            self.digits()?;
+        }
+
+        Ok(Json::Number(self.input[start..self.pos].to_string()))
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self.input.get(self.pos..self.pos + 4).ok_or_else(|| self.error("four hexadecimal digits"))?;
+        let value = u32::from_str_radix(digits, 16).map_err(|_| self.error("four hexadecimal digits"))?;
+
+        self.pos += 4;
+
+        Ok(value)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let mut result = String::new();
+
+        self.expect("\"")?;
+
+        loop {
+            let ch = self.peek().ok_or_else(|| self.error("'\"'"))?;
+
+            self.pos += ch.len_utf8();
+
+            match ch {
+                '"' => return Ok(result),
+                '\\' => {
+                    let escaped = self.peek().ok_or_else(|| self.error("an escape sequence"))?;
+
+                    self.pos += escaped.len_utf8();
+
+                    match escaped {
+                        '"' | '\\' | '/' => result.push(escaped),
+                        'b' => result.push('\u{8}'),
+                        'f' => result.push('\u{c}'),
+                        'n' => result.push('\n'),
+                        'r' => result.push('\r'),
+                        't' => result.push('\t'),
+                        'u' => {
+                            let mut code = self.hex4()?;
+
+                            // Characters outside of the BMP are encoded as surrogate pairs
+                            if (0xD800..0xDC00).contains(&code) {
+                                self.expect("\\u")?;
+
+                                let low = self.hex4()?;
+
+                                if !(0xDC00..=0xDFFF).contains(&low) {
+                                    self.pos -= 4;
+                                    return Err(self.error("a low surrogate"))
+                                }
+
+                                code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                            }
+
+                            result.push(::std::char::from_u32(code).ok_or_else(|| self.error("a valid character"))?);
+                        },
+
+                        _ => return Err(self.error("an escape sequence"))
+                    }
+                },
+                ch if (ch as u32) < 0x20 => return Err(self.error("'\"'")),
+                ch => result.push(ch)
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        let mut values = Vec::new();
+
+        self.expect("[")?;
+        self.skip_whitespace();
+
+        if self.eat("]") {
+            return Ok(Json::Array(values))
+        }
+
+        loop {
+            values.push(self.value()?);
+            self.skip_whitespace();
+
+            if self.eat("]") {
+                return Ok(Json::Array(values))
+            }
+
+            self.expect(",")?;
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        let mut entries = Vec::new();
+
+        self.expect("{")?;
+        self.skip_whitespace();
+
+        if !self.eat("}") {
+            loop {
+                self.skip_whitespace();
+
+                let key = self.string()?;
+
+                self.skip_whitespace();
+                self.expect(":")?;
+
+                let value = self.value()?;
+
+                entries.push((key, value));
+                self.skip_whitespace();
+
+                if self.eat("}") {
+                    break
+                }
+
+                self.expect(",")?;
+            }
+        }
+
+        // Objects with a single "$nix" string represent raw Nix expressions
+        if entries.len() == 1 && entries[0].0 == "$nix" {
+            if let Json::String(ref source) = entries[0].1 {
+                return Ok(Json::Nix(source.clone()))
+            }
+        }
+
+        Ok(Json::Object(entries))
+    }
+}
+
+/// Insert the given value at the given path in an object, merging it with the objects
+/// that already exist.
+fn insert(entries: &mut Vec<(String, Json)>, path: &[String], value: Json) {
+    let (key, rest) = path.split_first().unwrap();
+
+    if let Some(&mut (_, ref mut existing)) = entries.iter_mut().find(|&&mut (ref k, _)| k == key) {
+        match (existing, value) {
+            (&mut Json::Object(ref mut existing), Json::Object(entries)) if rest.is_empty() => {
+                for (key, value) in entries {
+                    insert(existing, &[key], value);
+                }
+            },
+            (&mut Json::Object(ref mut existing), value) if !rest.is_empty() => insert(existing, rest, value),
+            (existing, value) => *existing = nest(rest, value)
+        }
+
+        return
+    }
+
+    entries.push((key.clone(), nest(rest, value)));
+}
+
+/// Return the given value nested in objects with the given keys.
+fn nest(path: &[String], value: Json) -> Json {
+    path.iter().rev().fold(value, |value, key| Json::Object(vec![(key.clone(), value)]))
+}
+
+/// Convert a Nix float to a valid JSON number.
+fn normalize_float(value: &str) -> String {
+    if value.starts_with('.') {
+        format!("0{}", value)
+    } else {
+        value.to_string()
+    }
+}
+
+/// Write the given string as a JSON string.
+fn write_str(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    f.write_str("\"")?;
+
+    for ch in s.chars() {
+        match ch {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            ch if (ch as u32) < 0x20 => write!(f, "\\u{:04x}", ch as u32)?,
+            ch => write!(f, "{}", ch)?
+        }
+    }
+
+    f.write_str("\"")
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Number(ref value) => f.write_str(value),
+            Json::String(ref value) => write_str(f, value),
+
+            Json::Array(ref values) => {
+                f.write_str("[")?;
+
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+
+                    write!(f, "{}", value)?;
+                }
+
+                f.write_str("]")
+            },
+
+            Json::Object(ref entries) => {
+                f.write_str("{")?;
+
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+
+                    write_str(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+
+                f.write_str("}")
+            },
+
+            Json::Nix(ref source) => {
+                f.write_str("{\"$nix\":")?;
+                write_str(f, source)?;
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nix_to_json(source: &str) -> Result<String, Error> {
+        Json::from_source(source).map(|json| json.to_string())
+    }
+
+    #[test]
+    fn test_literals() {
+        assert_eq!(nix_to_json("null"), Ok("null".to_string()));
+        assert_eq!(nix_to_json("true"), Ok("true".to_string()));
+        assert_eq!(nix_to_json("-42"), Ok("-42".to_string()));
+        assert_eq!(nix_to_json("1.5"), Ok("1.5".to_string()));
+        assert_eq!(nix_to_json(r#""a \"b\"\n""#), Ok(r#""a \"b\"\n""#.to_string()));
+        assert_eq!(nix_to_json("[ 1 (2) \"3\" ]"), Ok(r#"[1,2,"3"]"#.to_string()));
+    }
+
+    #[test]
+    fn test_sets() {
+        assert_eq!(nix_to_json("{ a = 1; b.c = true; b.d = [ ]; \"e.f\" = null; }"),
+                   Ok(r#"{"a":1,"b":{"c":true,"d":[]},"e.f":null}"#.to_string()));
+        assert_eq!(nix_to_json("{ a = { b = 1; }; a.c = 2; }"),
+                   Ok(r#"{"a":{"b":1,"c":2}}"#.to_string()));
+        assert_eq!(nix_to_json("{ inherit a; inherit (pkgs) b; }"),
+                   Ok(r#"{"a":{"$nix":"a"},"b":{"$nix":"pkgs.b"}}"#.to_string()));
+        assert_eq!(nix_to_json("{ ${a} = 1; }"), Ok(r#"{"$nix":"{ ${a} = 1; }"}"#.to_string()));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Json::parse(" null "), Ok(Json::Null));
+        assert_eq!(Json::parse("[1, -2.5e3, true]"),
+                   Ok(Json::Array(vec![Json::Number("1".to_string()), Json::Number("-2.5e3".to_string()), Json::Bool(true)])));
+        assert_eq!(Json::parse(r#""a\"\u00e9\ud83d\ude00""#), Ok(Json::String("a\"é😀".to_string())));
+        assert_eq!(Json::parse(r#"{"a": {}, "$nix": 1}"#),
+                   Ok(Json::Object(vec![("a".to_string(), Json::Object(vec![])), ("$nix".to_string(), Json::Number("1".to_string()))])));
+        assert_eq!(Json::parse(r#"{"$nix": "pkgs.git"}"#), Ok(Json::Nix("pkgs.git".to_string())));
+
+        assert!(Json::parse("[1,]").is_err());
+        assert!(Json::parse("01").is_err());
+        assert!(Json::parse("{} {}").is_err());
+        assert_eq!(Json::parse(r#""\ud800\u0041""#), Err(Error::InvalidValue("Invalid JSON value: expected a low surrogate at position 9.".to_string())));
+        assert!(Json::parse(r#""\ud800""#).is_err());
+        assert!(Json::parse(r#""\udc00""#).is_err());
+    }
+
+    #[test]
+    fn test_to_nix() {
+        fn to_nix(json: &str) -> String {
+            Json::parse(json).unwrap().to_nix("  ", "  ")
+        }
+
+        assert_eq!(to_nix(r#""say \"${hi}\"\n""#), r#""say \"\${hi}\"\n""#);
+        assert_eq!(to_nix("[1, -2, 1e3, \"a\"]"), r#"[ 1 (-2) 1.0e3 "a" ]"#);
+        assert_eq!(to_nix(r#"[{"$nix": "pkgs.git"}, {"$nix": "f x"}]"#), "[ pkgs.git (f x) ]");
+        assert_eq!(to_nix("{}"), "{ }");
+        assert_eq!(to_nix(r#"{"a": [{"b": null}], "c.d": true, "if": 1}"#),
+                   "{\n    a = [\n      {\n        b = null;\n      }\n    ];\n    \"c.d\" = true;\n    \"if\" = 1;\n  }");
+    }
+
+    #[test]
+    fn test_expressions() {
+        assert_eq!(nix_to_json("with pkgs; [ git ]"), Ok(r#"{"$nix":"with pkgs; [ git ]"}"#.to_string()));
+        assert_eq!(nix_to_json("[ ./a.nix (f x) ]"), Ok(r#"[{"$nix":"./a.nix"},{"$nix":"f x"}]"#.to_string()));
+    }
+}
@@ -0,0 +1,877 @@
+//! Querying and modification of `.nix` files, keeping their formatting and comments intact.
+//!
+//! Files are loaded into a [`Document`], whose values are designated by a [`Path`] such as
+//! `services.nginx.enable` or `fileSystems[*].device`:
+//!
+//! ```
+//! use nixcfg::{Document, Path};
+//!
+//! let mut document = Document::parse("{ a = 1; }".to_string()).unwrap();
+//! let path = Path::parse("b.c").unwrap();
+//!
+//! document.set(&path, "true").unwrap();
+//!
+//! assert_eq!(document.source(), "{ a = 1; b.c = true; }");
+//! assert_eq!(document.get(&path).unwrap().source, "true");
+//! ```
+//!
+//! [`Document`]: struct.Document.html
+//! [`Path`]: struct.Path.html
+
+extern crate rnix;
+
+mod document;
+mod error;
+mod eval;
+pub mod imports;
+mod instantiate;
+mod json;
+mod path;
+
+use std::ops::Range;
+use std::panic;
+use std::path::PathBuf;
+
+use rnix::{NodeOrToken, StrPart, SyntaxKind, SyntaxNode, SyntaxToken, TextRange, AST};
+use rnix::SyntaxKind::*;
+use rnix::parser::ParseError;
+use rnix::types::{Str, TypedNode};
+
+use path::{attribute_names, format_name, format_path, resolve, AttrName, Entry, Match};
+
+pub use document::{Document, Location, Value};
+pub use error::Error;
+pub use json::Json;
+pub use path::Path;
+
+
+/// Options affecting the way paths are resolved.
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    /// The path of the input file, used when reporting locations.
+    pub file: PathBuf,
+
+    /// Whether to search the whole file for paths made of attribute names.
+    pub deep: bool,
+
+    /// The definitions to use for values that are defined several times.
+    pub selection: Selection
+}
+
+/// The definitions to use for values that are defined several times, such as `a.b = 1;`
+/// and `a = { b = 2; };`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Selection {
+    /// Fail, since editing any of them would be arbitrary.
+    #[default]
+    Unique,
+
+    /// Use all of them.
+    All,
+
+    /// Use the first one.
+    First,
+
+    /// Use the n-th one, starting at 1.
+    Nth(usize)
+}
+
+
+/// Parse the given source, failing with a message pointing to the location of the first
+/// error if it is invalid.
+fn parse(source: &str, what: &str) -> Result<AST, Error> {
+    // The parser is not supposed to panic, but we would rather report it than crash if it does
+    let ast = panic::catch_unwind(|| rnix::parse(source)).map_err(|payload| {
+        let reason = payload.downcast_ref::<String>()
+                            .map(|reason| reason.as_str())
+                            .or_else(|| payload.downcast_ref::<&str>().cloned())
+                            .unwrap_or("unknown error");
+
+        Error::Unsupported(format!("Unable to parse {}: the parser failed with '{}'.", what, reason))
+    })?;
+
+    // Errors may span several tokens, so only the first one is displayed
+    let root = ast.node();
+    let text = |range: TextRange| match root.token_at_offset(range.start()).right_biased() {
+        Some(token) => token.text().to_string(),
+        None => source[text_range(range)].to_string()
+    };
+
+    let (pos, err) = match ast.errors().into_iter().next() {
+        None => return Ok(ast),
+
+        Some(ParseError::Unexpected(range)) => (Some(range), format!("unexpected '{}'", text(range))),
+        Some(ParseError::UnexpectedExtra(range)) =>
+            (Some(range), format!("unexpected '{}' after the end of the expression", text(range))),
+        Some(ParseError::UnexpectedWanted(_, range, expected)) =>
+            (Some(range), format!("expected {}, found '{}'", describe_tokens(&expected), text(range))),
+        Some(ParseError::UnexpectedDoubleBind(range)) =>
+            (Some(range), "the arguments of the function are bound to several names".to_string()),
+        Some(ParseError::UnexpectedEOFWanted(expected)) => (None, format!("expected {}, found end of file", describe_tokens(&expected))),
+        Some(ParseError::DuplicatedArgs(range, name)) => (Some(range), format!("argument '{}' is defined several times", name)),
+        Some(ParseError::UnexpectedEOF) => (None, "unexpected end of file".to_string()),
+        Some(err) => (None, err.to_string())
+    };
+
+    // Errors without location happen at the end of the input
+    let pos = pos.map(|range| text_range(range).start).unwrap_or(source.len());
+    let (line, column) = locate(source, pos);
+    let line_text = source.lines().nth(line - 1).unwrap_or("");
+
+    let message = format!("Unable to parse {} at {}:{}: {}.\n{}\n{}^",
+                          what, line, column, err, line_text, " ".repeat(column - 1));
+
+    Err(Error::Parse { message, line, column })
+}
+
+/// Describe the given kinds of tokens expected by the parser, such as `';' or '}'`.
+fn describe_tokens(kinds: &[SyntaxKind]) -> String {
+    // All the tokens starting an expression are expected where a value is missing
+    if kinds.contains(&TOKEN_PAREN_OPEN) && kinds.contains(&TOKEN_IDENT) {
+        return "an expression".to_string()
+    }
+
+    let mut names: Vec<_> = kinds.iter()
+                                 .filter(|&&kind| kind != TOKEN_STRING_CONTENT)
+                                 .map(|&kind| token_name(kind))
+                                 .collect();
+
+    names.dedup();
+
+    match names.pop() {
+        Some(last) if names.is_empty() => last,
+        Some(last) => format!("{} or {}", names.join(", "), last),
+        None => "more input".to_string()
+    }
+}
+
+/// Return the name of the given kind of token, as displayed in errors.
+fn token_name(kind: SyntaxKind) -> String {
+    let symbol = match kind {
+        TOKEN_ASSERT => "assert",
+        TOKEN_ELSE => "else",
+        TOKEN_IF => "if",
+        TOKEN_IN => "in",
+        TOKEN_INHERIT => "inherit",
+        TOKEN_LET => "let",
+        TOKEN_REC => "rec",
+        TOKEN_THEN => "then",
+        TOKEN_WITH => "with",
+        TOKEN_CURLY_B_OPEN => "{",
+        TOKEN_CURLY_B_CLOSE | TOKEN_DYNAMIC_END | TOKEN_INTERPOL_END => "}",
+        TOKEN_SQUARE_B_OPEN => "[",
+        TOKEN_SQUARE_B_CLOSE => "]",
+        TOKEN_PAREN_OPEN => "(",
+        TOKEN_PAREN_CLOSE => ")",
+        TOKEN_ASSIGN => "=",
+        TOKEN_AT => "@",
+        TOKEN_COLON => ":",
+        TOKEN_COMMA => ",",
+        TOKEN_DOT => ".",
+        TOKEN_ELLIPSIS => "...",
+        TOKEN_QUESTION => "?",
+        TOKEN_SEMICOLON => ";",
+        TOKEN_DYNAMIC_START | TOKEN_INTERPOL_START => "${",
+        TOKEN_IDENT => return "an identifier".to_string(),
+        TOKEN_STRING_START => return "a string".to_string(),
+        TOKEN_STRING_END => return "the end of the string".to_string(),
+        kind => return format!("{:?}", kind)
+    };
+
+    format!("'{}'", symbol)
+}
+
+/// Return the line and column (both starting at 1) of the given byte position.
+fn locate(source: &str, pos: usize) -> (usize, usize) {
+    let line_start = source[..pos].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let line = source[..pos].matches('\n').count() + 1;
+    let column = source[line_start..pos].chars().count() + 1;
+
+    (line, column)
+}
+
+/// Return the attribute names of the given path if they should be searched in the whole
+/// file, or `None` if the path should be resolved from the top-level attribute set.
+fn deep_names<'a>(segments: &'a [path::Segment], options: &Options) -> Option<Vec<&'a str>> {
+    if options.deep {
+        path::names(segments)
+    } else {
+        None
+    }
+}
+
+/// Return all values matching the given path, alongside the entries defining them.
+fn find_matches(root: &SyntaxNode, segments: &[path::Segment], content: &str, options: &Options) -> Result<Vec<Match>, Error> {
+    let parts = match deep_names(segments, options) {
+        Some(parts) => parts,
+        None => return resolve(root, segments, content)
+    };
+
+    let mut entries = Vec::new();
+
+    collect_entries(root, &parts, 0, content, &mut entries)?;
+
+    if entries.is_empty() {
+        // Values such as the arguments of function calls are not defined by entries
+        entries.extend(find_node(root, &parts, 0, content)?.map(|node| (Vec::new(), node)));
+    }
+
+    if entries.is_empty() {
+        Ok(Vec::new())
+    } else {
+        Ok(vec![Match { path: format_path(&parts), entries }])
+    }
+}
+
+/// Return all values matching the given path, failing if there are none.
+fn find_all(root: &SyntaxNode, path: &Path, content: &str, options: &Options) -> Result<Vec<Match>, Error> {
+    let matches = find_matches(root, path.segments(), content, options)?;
+
+    if matches.is_empty() {
+        Err(no_match(root, path, content, options))
+    } else {
+        Ok(matches)
+    }
+}
+
+/// Return whether the given match is defined several times, rather than across several
+/// entries.
+fn is_ambiguous(m: &Match) -> bool {
+    m.entries.len() > 1 && m.entries.iter().all(|(key, _)| key.is_empty())
+}
+
+/// Return the given match, only keeping the definitions chosen by the options if it is
+/// defined several times.
+fn select_definitions(m: Match, content: &str, options: &Options) -> Result<Match, Error> {
+    if !is_ambiguous(&m) {
+        return Ok(m)
+    }
+
+    let count = m.entries.len();
+    let entries = match options.selection {
+        Selection::Unique => {
+            let locations: Vec<_> = m.entries.iter()
+                                             .map(|(_, node)| {
+                                                 let (line, column) = locate(content, definition_start(node));
+
+                                                 format!("{}:{}", line, column)
+                                             })
+                                             .collect();
+
+            return Err(Error::Ambiguous(format!("Path '{}' is defined {} times (at {}); use --all, --first or --nth to choose \
+                                                 which definitions to use.", m.path, count, locations.join(", "))))
+        },
+
+        Selection::All => m.entries,
+        Selection::First => m.entries[..1].to_vec(),
+        Selection::Nth(n) => match m.entries.get(n - 1) {
+            Some(entry) => vec![entry.clone()],
+            None => return Err(Error::NotFound(format!("Path '{}' is only defined {} times.", m.path, count)))
+        }
+    };
+
+    Ok(Match { path: m.path, entries })
+}
+
+/// Format the given attribute names like in the key of an entry, keeping the source of the
+/// names computed at runtime.
+fn format_key(key: &[AttrName], content: &str) -> String {
+    key.iter()
+       .map(|name| match *name {
+           AttrName::Static(ref name) => format_name(name),
+           AttrName::Dynamic(ref node) => content[span_range(node)].to_string()
+       })
+       .collect::<Vec<_>>()
+       .join(".")
+}
+
+/// Return the given options, changed to use all the definitions of values defined several
+/// times unless specific ones were chosen, for commands that list them.
+fn all_definitions(options: &Options) -> Options {
+    match options.selection {
+        Selection::Unique => Options { selection: Selection::All, ..options.clone() },
+        _ => options.clone()
+    }
+}
+
+/// Return the start of the definition of the given value, which is the start of its entry
+/// if it has one.
+fn definition_start(node: &SyntaxNode) -> usize {
+    match node.parent() {
+        Some(ref entry) if entry.kind() == NODE_KEY_VALUE => span_range(entry).start,
+        _ => span_range(node).start
+    }
+}
+
+/// Return the error reported when nothing matches the given path.
+fn no_match(root: &SyntaxNode, path: &Path, content: &str, options: &Options) -> Error {
+    let hidden = match deep_names(path.segments(), options) {
+        // Paths searched in the whole file may be hidden by any attribute
+        Some(_) => root.descendants()
+                       .filter(|node| node.kind() == NODE_KEY_VALUE)
+                       .filter_map(|entry| entry.children().next())
+                       .flat_map(|key| attribute_names(&key))
+                       .filter_map(|name| match name {
+                           AttrName::Dynamic(node) => Some(node),
+                           AttrName::Static(_) => None
+                       })
+                       .collect(),
+        None => path::hidden_names(root, path.segments(), content).unwrap_or_default()
+    };
+
+    Error::NotFound(format!("No value matches path '{}'.{}", path, dynamic_note(hidden.first(), content)))
+}
+
+/// Return a note about the given attribute name computed at runtime, which may hide the
+/// value that was looked for, or an empty string if there is none.
+fn dynamic_note(name: Option<&SyntaxNode>, content: &str) -> String {
+    match name {
+        Some(node) => {
+            let (line, column) = locate(content, span_range(node).start);
+
+            format!(" The attribute name '{}' at {}:{} is computed at runtime, and cannot be resolved.",
+                    &content[span_range(node)], line, column)
+        },
+        None => String::new()
+    }
+}
+
+/// Return the given child of the given node, failing if the node does not have it.
+fn child(node: &SyntaxNode, i: usize, content: &str) -> Result<SyntaxNode, Error> {
+    node.children().nth(i).ok_or_else(|| unsupported(node, content))
+}
+
+/// Return the error reported when the given node is not shaped as expected, naming the
+/// construct and its location.
+fn unsupported(node: &SyntaxNode, content: &str) -> Error {
+    let (line, column) = locate(content, span_range(node).start.min(content.len()));
+
+    Error::Unsupported(format!("Unsupported {} at {}:{}: its structure is not recognized.",
+                               construct_name(node.kind()), line, column))
+}
+
+/// Return the name of the given kind of node, as displayed in errors.
+fn construct_name(kind: SyntaxKind) -> String {
+    match kind {
+        NODE_APPLY => "function call".to_string(),
+        NODE_ASSERT => "assertion".to_string(),
+        NODE_BIN_OP => "binary operation".to_string(),
+        NODE_DYNAMIC => "dynamic attribute name".to_string(),
+        NODE_IF_ELSE => "if expression".to_string(),
+        NODE_INHERIT => "inherit".to_string(),
+        NODE_INHERIT_FROM => "inherit source".to_string(),
+        NODE_KEY => "attribute name".to_string(),
+        NODE_LAMBDA => "function".to_string(),
+        NODE_LET_IN => "let expression".to_string(),
+        NODE_LIST => "list".to_string(),
+        NODE_OR_DEFAULT | NODE_SELECT => "attribute selection".to_string(),
+        NODE_PAREN => "parenthesized expression".to_string(),
+        NODE_ATTR_SET => "attribute set".to_string(),
+        NODE_KEY_VALUE => "attribute set entry".to_string(),
+        NODE_STRING_INTERPOL => "string interpolation".to_string(),
+        NODE_UNARY_OP => "unary operation".to_string(),
+        NODE_WITH => "with expression".to_string(),
+        kind => format!("{:?} expression", kind)
+    }
+}
+
+/// Return the name of the given identifier, or `None` if the node is not an identifier.
+fn ident_name(node: &SyntaxNode) -> Option<String> {
+    if node.kind() == NODE_IDENT {
+        Some(node.text().to_string())
+    } else {
+        None
+    }
+}
+
+/// Return the content of the given string, or `None` if the node is not a string or if
+/// its content is computed at runtime.
+fn static_string(node: &SyntaxNode) -> Option<String> {
+    let mut content = String::new();
+
+    for part in Str::cast(node.clone())?.parts() {
+        match part {
+            StrPart::Literal(literal) => content.push_str(&literal),
+            StrPart::Ast(_) => return None
+        }
+    }
+
+    Some(content)
+}
+
+/// Find the first value matching the given path, looking through function calls such as
+/// `mkIf cond { ... }`.
+fn find_node(node: &SyntaxNode, parts: &[&str], i: usize, content: &str) -> Result<Option<SyntaxNode>, Error> {
+    /// Try to match the i'th child with the given path. On success, the r'th child will be
+    /// returned.
+    macro_rules! try_match {
+        ( $i: expr => $r: expr ) => ({
+            let ident_node = child(node, $i, content)?;
+            let j = try_advance_ident(&ident_node, parts, i);
+
+            if j > i {
+                // We did advance, which means we might have a match
+                let res = child(node, $r, content)?;
+
+                return if j == parts.len() {
+                    // We even got to the end of the path, which means we have a complete match!
+                    Ok(Some(res))
+                } else {
+                    // We're not at the end of the path, so we continue recursively
+                    find_node(&res, parts, j, content)
+                }
+            }
+        });
+    }
+
+    match node.kind() {
+        NODE_APPLY => try_match!(0 => 1),
+        NODE_KEY_VALUE => try_match!(0 => 1),
+        _ => ()
+    }
+
+    // Try recursively on children
+    for child in node.children() {
+        if let Some(found) = find_node(&child, parts, i, content)? {
+            return Ok(Some(found))
+        }
+    }
+
+    Ok(None)
+}
+
+/// Collect all entries whose key starts with the given path, alongside the part of their
+/// key that comes after the path. Entries that exactly match the path have an empty key.
+fn collect_entries(node: &SyntaxNode, parts: &[&str], i: usize, content: &str,
+                   entries: &mut Vec<Entry>) -> Result<(), Error> {
+    match node.kind() {
+        NODE_KEY_VALUE => {
+            let key = child(node, 0, content)?;
+            let value = child(node, 1, content)?;
+            let names = attribute_names(&key);
+
+            let matched = names.iter()
+                               .zip(&parts[i..])
+                               .take_while(|&(name, part)| name.matches(part))
+                               .count();
+
+            if matched == 0 {
+                // No match, so we continue recursively
+            } else if matched == names.len() {
+                // The whole key matched
+                if i + matched == parts.len() {
+                    entries.push((Vec::new(), value));
+
+                    return Ok(())
+                } else {
+                    return collect_entries(&value, parts, i + matched, content, entries)
+                }
+            } else if i + matched == parts.len() {
+                // The whole path matched, and the key continues after it
+                entries.push((names[matched..].to_vec(), value));
+
+                return Ok(())
+            } else {
+                // The key and path diverge
+                return Ok(())
+            }
+        },
+
+        NODE_APPLY => {
+            let j = try_advance_ident(&child(node, 0, content)?, parts, i);
+
+            if j == parts.len() {
+                entries.push((Vec::new(), child(node, 1, content)?));
+
+                return Ok(())
+            } else if j > i {
+                return collect_entries(&child(node, 1, content)?, parts, j, content, entries)
+            }
+        },
+
+        _ => ()
+    }
+
+    // Try recursively on children
+    for child in node.children() {
+        collect_entries(&child, parts, i, content, entries)?;
+    }
+
+    Ok(())
+}
+
+/// Merge the given entries into a single attribute set, and return its source.
+fn merge_entries(entries: &[Entry], content: &str, path: &str) -> Result<String, Error> {
+    let mut merged = Vec::new();
+
+    for (key, value) in entries {
+        if !key.is_empty() {
+            merged.push(format!("{} = {};", format_key(key, content), &content[span_range(value)]));
+        } else if value.kind() == NODE_ATTR_SET {
+            // Inline the entries of the set
+            for child in value.children() {
+                if child.kind() == NODE_KEY_VALUE || child.kind() == NODE_INHERIT {
+                    merged.push(content[span_range(&child)].to_string());
+                }
+            }
+        } else {
+            return Err(Error::InvalidValue(format!("Path '{}' is defined both as a value and as an attribute set.", path)))
+        }
+    }
+
+    Ok(format!("{{ {} }}", merged.join(" ")))
+}
+
+/// Insert the given values in the empty set or list delimited by the given brackets,
+/// putting each value on its own line if the brackets are on different lines.
+fn insert_into_empty(content: &mut String, open: Range<usize>, close: Range<usize>, values: &[String]) {
+    match empty_indent(content, open.clone(), close.clone()) {
+        Some(indent) => {
+            let line_start = close.start - leading_whitespace(content, close.start).len();
+            let eol = line_ending(content);
+            let text: String = values.iter()
+                                     .map(|value| format!("{}{}{}", indent, value, eol))
+                                     .collect();
+
+            content.insert_str(line_start, &text);
+        },
+
+        None => if content[open.end..close.start].trim().is_empty() {
+            content.replace_range(open.end..close.start, &format!(" {} ", values.join(" ")));
+        } else {
+            content.insert_str(close.start, &format!("{} ", values.join(" ")));
+        }
+    }
+}
+
+/// Return the indentation of values inserted in the empty set or list delimited by the
+/// given brackets, or `None` if they should be inserted inline.
+fn empty_indent(content: &str, open: Range<usize>, close: Range<usize>) -> Option<String> {
+    match line_indent(content, close.start) {
+        Some(close_indent) if content[open.end..close.start].contains('\n') =>
+            Some(close_indent + &indent_unit(content)),
+        _ => None
+    }
+}
+
+/// Find the attribute set in which the given path should be inserted, returning it and
+/// the number of parts of the path that it already represents.
+fn find_insertion_point(root: &SyntaxNode, parts: &[&str], content: &str, options: &Options) -> Result<(SyntaxNode, usize), Error> {
+    for prefix_len in (1..parts.len()).rev() {
+        let prefix = &parts[..prefix_len];
+        let node = if options.deep {
+            find_node(root, prefix, 0, content)?
+        } else {
+            // Prefixes defined across several entries are skipped, since they cannot hold the
+            // new entry; it is then inserted next to them instead
+            let segments: Vec<_> = prefix.iter().map(|name| path::Segment::Name(name.to_string())).collect();
+
+            resolve(root, &segments, content)?.first().and_then(Match::node)
+        };
+
+        if let Some(node) = node {
+            return match find_expr(&node, NODE_ATTR_SET) {
+                Some(set) => Ok((set, prefix_len)),
+                None => Err(Error::InvalidValue(format!("Cannot insert '{}' into '{}', which is not an attribute set.",
+                                                        format_path(&parts[prefix_len..]), format_path(&parts[..prefix_len]))))
+            }
+        }
+    }
+
+    // No prefix of the path exists, so we insert it in the top-level set
+    find_expr(root, NODE_ATTR_SET)
+        .map(|set| (set, 0))
+        .ok_or_else(|| Error::InvalidValue("Unable to find the top-level attribute set of the input file.".to_string()))
+}
+
+/// Find the expression of the given kind denoted by the given node, looking through
+/// function definitions, `let` and `with` expressions, parentheses and the root of the file.
+fn find_expr(node: &SyntaxNode, kind: SyntaxKind) -> Option<SyntaxNode> {
+    match node.kind() {
+        k if k == kind => Some(node.clone()),
+
+        // The body of the expression is its last child
+        NODE_LAMBDA | NODE_LET_IN | NODE_WITH | NODE_PAREN | NODE_APPLY | NODE_ROOT =>
+            node.last_child().and_then(|body| find_expr(&body, kind)),
+
+        _ => None
+    }
+}
+
+/// Insert the entry `path = value;` into the given set, right after the entry that
+/// is the most similar to it, and with the same indentation.
+///
+/// The value is rendered by the given function, given the indentation of its entry.
+fn insert_entry(set: &SyntaxNode, path: &[&str], value: &dyn Fn(&str) -> String, content: &mut String) -> Result<(), Error> {
+    let entry = |indent: &str| format!("{} = {};", format_path(path), value(indent));
+
+    let entries: Vec<_> = set.children()
+                             .filter(|node| node.kind() == NODE_KEY_VALUE || node.kind() == NODE_INHERIT)
+                             .collect();
+
+    // Choose the last entry that shares the longest prefix with the new entry
+    let mut anchor = None;
+    let mut anchor_prefix_len = 0;
+
+    for node in entries {
+        let prefix_len = if node.kind() == NODE_KEY_VALUE {
+            let key = child(&node, 0, content)?;
+
+            attribute_names(&key).iter()
+                                 .zip(path)
+                                 .take_while(|&(name, part)| name.matches(part))
+                                 .count()
+        } else {
+            0
+        };
+
+        if anchor.is_none() || prefix_len >= anchor_prefix_len {
+            anchor = Some(node);
+            anchor_prefix_len = prefix_len;
+        }
+    }
+
+    match anchor {
+        Some(anchor) => {
+            let range = span_range(&anchor);
+            let indent = line_indent(content, range.start);
+
+            // Insert on a new line if the anchor is on its own line, or inline otherwise
+            match indent {
+                Some(indent) => {
+                    let pos = line_end(content, range.end);
+                    let text = format!("{}{}{}", line_ending(content), indent, entry(&indent));
+
+                    content.insert_str(pos, &text);
+                },
+                None => {
+                    let text = format!(" {}", entry(leading_whitespace(content, range.start)));
+
+                    content.insert_str(range.end, &text);
+                }
+            }
+        },
+
+        None => {
+            // The set is empty, so we have to guess the indentation
+            let (open, close) = match (token(set, TOKEN_CURLY_B_OPEN), token(set, TOKEN_CURLY_B_CLOSE)) {
+                (Some(open), Some(close)) => (token_range(&open), token_range(&close)),
+                _ => return Err(unsupported(set, content))
+            };
+            let entry = match empty_indent(content, open.clone(), close.clone()) {
+                Some(indent) => entry(&indent),
+                None => entry(leading_whitespace(content, open.start))
+            };
+
+            insert_into_empty(content, open, close, &[entry]);
+        }
+    }
+
+    Ok(())
+}
+
+/// Return the first token of the given kind among the direct children of the given node.
+fn token(node: &SyntaxNode, kind: SyntaxKind) -> Option<SyntaxToken> {
+    node.children_with_tokens()
+        .filter_map(|element| match element {
+            NodeOrToken::Token(token) => Some(token),
+            NodeOrToken::Node(_) => None
+        })
+        .find(|token| token.kind() == kind)
+}
+
+/// Return the whitespace preceding the given position on its line, or `None` if
+/// the position is preceded by something else.
+fn line_indent(content: &str, pos: usize) -> Option<String> {
+    let line_start = content[..pos].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let indent = &content[line_start..pos];
+
+    if indent.chars().all(|ch| ch == ' ' || ch == '\t') {
+        Some(indent.to_string())
+    } else {
+        None
+    }
+}
+
+/// Return the range of bytes to remove in order to remove the entry spanning the given
+/// range, including its line and the comments directly attached to it if it is on its own line.
+fn removal_range(content: &str, range: Range<usize>) -> Range<usize> {
+    let line_start = content[..range.start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let line_end = content[range.end..].find('\n').map(|i| range.end + i + 1).unwrap_or(content.len());
+
+    let before = content[line_start..range.start].trim();
+    let after = content[range.end..line_end].trim();
+
+    if before.is_empty() && (after.is_empty() || after.starts_with('#')) {
+        // The entry is on its own line, so we remove the line and the comments above it
+        let mut start = line_start;
+
+        while start > 0 {
+            let prev_line_start = content[..start - 1].rfind('\n').map(|i| i + 1).unwrap_or(0);
+
+            if !content[prev_line_start..start].trim_start().starts_with('#') {
+                break
+            }
+
+            start = prev_line_start;
+        }
+
+        start..line_end
+    } else {
+        // The entry shares its line with other code, so we only remove surrounding spaces
+        let trailing = content[range.end..].len() - content[range.end..].trim_start_matches(' ').len();
+        let leading = content[..range.start].len() - content[..range.start].trim_end_matches(' ').len();
+
+        if trailing > 0 {
+            range.start..range.end + trailing
+        } else {
+            range.start - leading..range.end
+        }
+    }
+}
+
+/// Return the whitespace at the start of the line containing the given position.
+fn leading_whitespace(content: &str, pos: usize) -> &str {
+    let line_start = content[..pos].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let line = &content[line_start..pos];
+
+    &line[..line.len() - line.trim_start().len()]
+}
+
+/// Return the source of the given node, with the indentation of the line it starts on
+/// removed from its other lines, so that multi-line values read as in the file. Lines
+/// starting within strings are kept as they are, since their indentation is part of the value.
+fn dedent(content: &str, node: &SyntaxNode) -> String {
+    let range = span_range(node);
+    let strings: Vec<_> = node.descendants_with_tokens()
+                              .filter_map(|element| match element {
+                                  NodeOrToken::Token(ref token) if token.kind() == TOKEN_STRING_CONTENT => Some(token_range(token)),
+                                  _ => None
+                              })
+                              .collect();
+    let in_string = |pos: usize| strings.iter().any(|string| string.start < pos && pos <= string.end);
+
+    let mut lines = Vec::new();
+    let mut pos = range.start;
+
+    for line in content[range.clone()].split('\n') {
+        lines.push((pos, line));
+        pos += line.len() + 1;
+    }
+
+    // Lines indented less than the first one only keep what they share
+    let mut indent = leading_whitespace(content, range.start);
+
+    for &(pos, line) in lines.iter().skip(1) {
+        if in_string(pos) || line.trim().is_empty() {
+            continue
+        }
+
+        let shared = indent.char_indices()
+                           .find(|&(i, ch)| !line[i..].starts_with(ch))
+                           .map(|(i, _)| i)
+                           .unwrap_or_else(|| indent.len());
+
+        indent = &indent[..shared];
+    }
+
+    lines.iter()
+         .enumerate()
+         .map(|(i, &(pos, line))| if i == 0 || in_string(pos) {
+             line
+         } else {
+             line.strip_prefix(indent).unwrap_or_else(|| line.trim_start())
+         })
+         .collect::<Vec<_>>()
+         .join("\n")
+}
+
+/// Return the smallest indentation used in the given source, defaulting to two spaces.
+fn indent_unit(content: &str) -> String {
+    content.lines()
+           .filter(|line| !line.trim().is_empty())
+           .map(|line| &line[..line.len() - line.trim_start().len()])
+           .filter(|indent| !indent.is_empty())
+           .min_by_key(|indent| if indent.starts_with('\t') { 0 } else { indent.len() })
+           .map(|indent| if indent.starts_with('\t') { "\t".to_string() } else { indent.to_string() })
+           .unwrap_or_else(|| "  ".to_string())
+}
+
+/// Return the line ending used in the given source, which is the one ending its first line.
+fn line_ending(content: &str) -> &'static str {
+    match content.find('\n') {
+        Some(i) if content[..i].ends_with('\r') => "\r\n",
+        _ => "\n"
+    }
+}
+
+/// Return the end of the line containing the given position if it is only followed by
+/// whitespace and comments, or the position itself otherwise.
+fn line_end(content: &str, pos: usize) -> usize {
+    let end = content[pos..].find('\n').map(|i| pos + i).unwrap_or(content.len());
+    let rest = content[pos..end].trim();
+
+    if rest.is_empty() || rest.starts_with('#') {
+        content[..end].trim_end().len()
+    } else {
+        pos
+    }
+}
+
+/// Return the range of bytes spanned by the given node in the source.
+fn span_range(node: &SyntaxNode) -> Range<usize> {
+    text_range(node.text_range())
+}
+
+/// Return the range of bytes spanned by the given token in the source.
+fn token_range(token: &SyntaxToken) -> Range<usize> {
+    text_range(token.text_range())
+}
+
+/// Return the range of bytes corresponding to the given range of the tree.
+fn text_range(range: TextRange) -> Range<usize> {
+    usize::from(range.start())..usize::from(range.end())
+}
+
+fn try_advance_ident(node: &SyntaxNode, parts: &[&str], i: usize) -> usize {
+    match node.kind() {
+        NODE_KEY | NODE_SELECT => {
+            // All the names must match, and names computed at runtime never do
+            let names = attribute_names(node);
+            let matches = names.len() <= parts.len() - i &&
+                          names.iter().zip(&parts[i..]).all(|(name, part)| name.matches(part));
+
+            if matches {
+                i + names.len()
+            } else {
+                i
+            }
+        }
+
+        // Try recursively on children
+        _ => node.children()
+                 .map(|child| try_advance_ident(&child, parts, i))
+                 .find(|j| *j > i)
+                 .unwrap_or(i)
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("fals;", "value").err(),
+                   Some(Error::Parse { message: "Unable to parse value at 1:5: unexpected ';' after the end of the expression.\n\
+                                                  fals;\n    ^".to_string(), line: 1, column: 5 }));
+        assert_eq!(parse("f x # Comment.\n", "value").err(), None);
+        assert_eq!(parse("{\n  a = \"b;\n}", "input file").err(),
+                   Some(Error::Parse { message: "Unable to parse input file at 3:2: expected the end of the string or '${', found end of file.\n}\n ^"
+                                               .to_string(), line: 3, column: 2 }));
+        assert_eq!(parse("{ a = ; }", "value").err().map(|err| err.to_string()),
+                   Some("Unable to parse value at 1:7: expected an expression, found ';'.\n{ a = ; }\n      ^".to_string()));
+    }
+}
@@ -1,14 +1,19 @@
-extern crate rnix;
+extern crate nixcfg;
 extern crate structopt;
 
-use std::fmt::Write as FmtWrite;
-use std::io::{stdin, Read, Seek, SeekFrom, Write};
-use std::path::PathBuf;
+mod diff;
 
-use rnix::tokenizer::TokenKind;
-use rnix::parser::{AST, Data as ASTData, ASTKind, ASTNode, NodeId};
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{stdin, ErrorKind, Read, Write};
+use std::ops::Range;
+use std::path::{Path as FilePath, PathBuf};
+use std::process;
+use std::str::FromStr;
 
-use structopt::{clap::AppSettings, StructOpt};
+use structopt::{clap::{self, AppSettings}, StructOpt};
+
+use nixcfg::{imports, Document, Error, Json, Location, Options, Path, Selection, Value};
 
 
 #[derive(Debug, StructOpt)]
@@ -26,6 +31,44 @@ pub struct Args {
     #[structopt(short = "i", long = "in-place")]
     in_place: bool,
 
+    /// Print a unified diff of the changes instead of the resulting file, and exit with code
+    /// 2 if anything changed.
+    #[structopt(short = "d", long = "diff")]
+    diff: bool,
+
+    /// Keep a copy of the original file when modifying it in place, named by appending the
+    /// given suffix (`~` by default) to its name.
+    #[structopt(long = "backup", value_name = "SUFFIX",
+                raw(min_values = "0", require_equals = "true", requires = "\"in_place\""))]
+    backup: Option<String>,
+
+    /// Search the whole file for attribute names, including `let` bindings and function
+    /// arguments, instead of only following its top-level attribute set.
+    #[structopt(long = "deep")]
+    deep: bool,
+
+    /// Also look for paths in the files imported by the input file through its `imports`
+    /// list, and apply commands to the file that defines them.
+    #[structopt(long = "follow-imports")]
+    follow_imports: bool,
+
+    /// Use all the definitions of values that are defined several times.
+    #[structopt(long = "all", raw(conflicts_with_all = "&[\"first\", \"nth\"]"))]
+    all: bool,
+
+    /// Use the first definition of values that are defined several times.
+    #[structopt(long = "first", raw(conflicts_with = "\"nth\""))]
+    first: bool,
+
+    /// Use the n-th definition (starting at 1) of values that are defined several times.
+    #[structopt(long = "nth", value_name = "N")]
+    nth: Option<usize>,
+
+    /// Format of the errors printed to stderr.
+    #[structopt(long = "error-format", value_name = "FORMAT", default_value = "human",
+                raw(possible_values = "&[\"human\", \"json\"]"))]
+    error_format: ErrorFormat,
+
     /// Command to execute.
     #[structopt(subcommand)]
     command: Command
@@ -38,7 +81,31 @@ pub enum Command {
     Get {
         /// The path of the value.
         #[structopt(name = "path")]
-        path: String
+        path: String,
+
+        /// Format of the output.
+        #[structopt(short = "o", long = "output", default_value = "nix",
+                    raw(possible_values = "&[\"nix\", \"json\"]"))]
+        output: OutputFormat,
+
+        /// Print the location of the definitions of the value instead of the value itself.
+        #[structopt(short = "l", long = "locate")]
+        locate: bool,
+
+        /// Evaluate the value, which must only depend on literals and on variables defined
+        /// in the file.
+        #[structopt(short = "e", long = "eval", raw(conflicts_with = "\"locate\""))]
+        eval: bool,
+
+        /// Evaluate the value with `nix-instantiate`, replacing the arguments of the functions
+        /// around it by stubs.
+        #[structopt(long = "nix-eval", raw(conflicts_with_all = "&[\"locate\", \"eval\"]"))]
+        nix_eval: bool,
+
+        /// The `nix-instantiate` command used by `--nix-eval`.
+        #[structopt(long = "nix-instantiate", value_name = "COMMAND", default_value = "nix-instantiate",
+                    raw(env = "\"NIXCFG_NIX_INSTANTIATE\""))]
+        nix_instantiate: String
     },
 
     /// Set the value at the given path.
@@ -54,99 +121,478 @@ pub enum Command {
 
         /// Do not strip last new-line character from input.
         #[structopt(short = "n", long = "keep-eol")]
-        keep_eol: bool
+        keep_eol: bool,
+
+        /// Parse the value as JSON instead of Nix.
+        #[structopt(short = "j", long = "json")]
+        json: bool
+    },
+
+    /// Delete the value at the given path.
+    #[structopt(name = "delete", raw(alias = "\"unset\""))]
+    Delete {
+        /// The path of the value.
+        #[structopt(name = "path")]
+        path: String,
+
+        /// Also delete the parent attribute sets that become empty.
+        #[structopt(short = "p", long = "prune")]
+        prune: bool
+    },
+
+    /// Query or modify the list at the given path.
+    #[structopt(name = "list")]
+    List {
+        /// List command to execute.
+        #[structopt(subcommand)]
+        command: ListCommand
+    },
+
+    /// Print the paths of all the values defined in the file, or in the value at the given
+    /// path, flattening nested attribute sets.
+    #[structopt(name = "paths")]
+    Paths {
+        /// The path of the value whose attributes should be printed.
+        #[structopt(name = "path")]
+        path: Option<String>,
+
+        /// Also print the values.
+        #[structopt(short = "v", long = "values")]
+        values: bool,
+
+        /// Format of the output.
+        #[structopt(short = "o", long = "output", default_value = "nix",
+                    raw(possible_values = "&[\"nix\", \"json\"]"))]
+        output: OutputFormat
+    },
+
+    /// Apply the commands of a script, one per line, and only write the result if they
+    /// all succeed.
+    #[structopt(name = "apply")]
+    Apply {
+        /// The script to apply. If not specified or `-`, it will be read from stdin.
+        #[structopt(name = "script", parse(from_os_str))]
+        script: Option<PathBuf>
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub enum ListCommand {
+    /// Add elements to the list at the given path.
+    #[structopt(name = "add")]
+    Add {
+        /// The path of the list.
+        #[structopt(name = "path")]
+        path: String,
+
+        /// The elements to add.
+        #[structopt(name = "values", required = true)]
+        values: Vec<String>,
+
+        /// Add the elements at the start of the list instead of its end.
+        #[structopt(short = "p", long = "prepend")]
+        prepend: bool,
+
+        /// Fail if one of the elements is already in the list.
+        #[structopt(short = "u", long = "unique")]
+        unique: bool
+    },
+
+    /// Remove elements from the list at the given path.
+    #[structopt(name = "remove")]
+    Remove {
+        /// The path of the list.
+        #[structopt(name = "path")]
+        path: String,
+
+        /// The elements to remove.
+        #[structopt(name = "values", required = true)]
+        values: Vec<String>
+    },
+
+    /// Print whether the list at the given path contains the given element.
+    #[structopt(name = "contains")]
+    Contains {
+        /// The path of the list.
+        #[structopt(name = "path")]
+        path: String,
+
+        /// The element to look for.
+        #[structopt(name = "value")]
+        value: String
+    }
+}
+
+impl Command {
+    /// Return the path the command applies to, if any.
+    fn path(&self) -> Option<&str> {
+        match *self {
+            Command::Get { ref path, .. } | Command::Set { ref path, .. } | Command::Delete { ref path, .. } |
+            Command::List { command: ListCommand::Add { ref path, .. } } |
+            Command::List { command: ListCommand::Remove { ref path, .. } } |
+            Command::List { command: ListCommand::Contains { ref path, .. } } => Some(path),
+
+            Command::Paths { ref path, .. } => path.as_ref().map(String::as_str),
+            Command::Apply { .. } => None
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    /// Nix source.
+    Nix,
+
+    /// JSON, with expressions that cannot be converted represented as `{ "$nix": "<source>" }`.
+    Json
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "nix" => Ok(OutputFormat::Nix),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!("Unknown output format '{}'.", s))
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ErrorFormat {
+    /// A message meant to be read by humans.
+    Human,
+
+    /// A JSON object with the kind of the error, its message and exit code, and its location
+    /// for parse errors.
+    Json
+}
+
+impl FromStr for ErrorFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "human" => Ok(ErrorFormat::Human),
+            "json" => Ok(ErrorFormat::Json),
+            _ => Err(format!("Unknown error format '{}'.", s))
+        }
     }
 }
 
 fn main() {
-    let args = Args::from_args();
+    let raw_args: Vec<_> = env::args_os().map(|arg| arg.to_string_lossy().into_owned()).collect();
+    let requested_format = requested_error_format(&raw_args);
+    let mut app = Args::clap();
+
+    if requested_format == ErrorFormat::Json {
+        app = app.global_setting(AppSettings::ColorNever);
+    }
+
+    let matches = match app.get_matches_safe() {
+        Ok(matches) => matches,
 
-    if let Err(err) = run(args) {
-        eprintln!("{}", err);
-        std::process::exit(1)
+        // Help and version messages are not errors, and are always displayed as usual
+        Err(ref err) if requested_format == ErrorFormat::Human || !err.use_stderr() => err.exit(),
+        Err(err) => {
+            eprintln!("{}", usage_error_to_json(&err));
+            process::exit(EXIT_INVALID_ARGUMENTS)
+        }
+    };
+    let mut args = Args::from_clap(&matches);
+
+    // `--backup` may be given without a suffix, in which case it has no value
+    if args.backup.is_none() && matches.is_present("backup") {
+        args.backup = Some("~".to_string());
+    }
+
+    let error_format = args.error_format;
+
+    match run(args) {
+        Ok(code) => process::exit(code),
+        Err(err) => {
+            match error_format {
+                ErrorFormat::Human => eprintln!("{}", err),
+                ErrorFormat::Json => eprintln!("{}", error_to_json(&err))
+            }
+
+            process::exit(exit_code(&err))
+        }
     }
 }
 
+/// Return the error format requested by the given command line arguments, which must be
+/// known before they are parsed in order to report errors in them.
+fn requested_error_format(args: &[String]) -> ErrorFormat {
+    let mut format = ErrorFormat::Human;
+
+    for (i, arg) in args.iter().enumerate() {
+        let value = match arg.strip_prefix("--error-format=") {
+            Some(value) => Some(value),
+            None if arg == "--error-format" => args.get(i + 1).map(String::as_str),
+            None => None
+        };
+
+        if let Some(Ok(value)) = value.map(str::parse) {
+            format = value;
+        }
+    }
+
+    format
+}
+
+
+/// Exit code used when the command line arguments are invalid.
+const EXIT_INVALID_ARGUMENTS: i32 = 1;
+
+/// Exit code used with `--diff` when the file was changed.
+const EXIT_CHANGED: i32 = 2;
+
+/// Exit code used when no value matches the given path.
+const EXIT_NOT_FOUND: i32 = 3;
+
+/// Exit code used when the given path matches several values or definitions.
+const EXIT_AMBIGUOUS: i32 = 4;
+
+/// Exit code used when the input file or a given value cannot be parsed.
+const EXIT_PARSE: i32 = 5;
+
+/// Exit code used when a given value is invalid, or when the value at the given path cannot
+/// be used for the command.
+const EXIT_INVALID_VALUE: i32 = 6;
+
+/// Exit code used when a file cannot be read or written, or a command cannot be run.
+const EXIT_IO: i32 = 7;
+
+/// Exit code used when the given path is invalid.
+const EXIT_INVALID_PATH: i32 = 8;
 
-fn run(args: Args) -> Result<(), String> {
-    let Args { in_place, input, command } = args;
-    
-    // Read file contents
-    let mut file = std::fs::File::open(&input)
-        .map_err(|err| format!("Unable to open file '{}': {}.", input.display(), err))?;
-    
-    let mut content = String::new();
+/// Exit code used when a value cannot be evaluated.
+const EXIT_EVAL: i32 = 9;
 
-    file.read_to_string(&mut content)
-        .map_err(|err| format!("Unable to read input file: {}.", err))?;
-    
-    // Parse file contents
-    let ast = rnix::parse(&content)
-        .map_err(|err| format!("Unable to parse input file: {}.", err))?;
+/// Exit code used when the input file contains a construct that cannot be handled.
+const EXIT_UNSUPPORTED: i32 = 10;
+
+
+/// Run the given command, and return the exit code of the process.
+fn run(args: Args) -> Result<i32, Error> {
+    let Args { in_place, diff, backup, deep, follow_imports, all, first, nth, input, command, .. } = args;
+    let selection = match (all, first, nth) {
+        (_, _, Some(0)) => return Err(Error::InvalidValue("Definitions are numbered starting at 1.".to_string())),
+        (_, _, Some(n)) => Selection::Nth(n),
+        (true, _, _) => Selection::All,
+        (_, true, _) => Selection::First,
+        _ => Selection::Unique
+    };
+
+    let mut document = Document::open(&input)?;
+
+    document.options = Options { file: input.clone(), deep, selection };
+
+    // Find the file defining the path among the imported files
+    if follow_imports {
+        let mut files = imports::load_imports(&input, document.source().to_string())?;
+        let i = defining_file(&files, &command, &document.options)?;
+        let (file, content) = files.swap_remove(i);
+        let options = Options { file, ..document.options };
+
+        document = Document::parse(content)?;
+        document.options = options;
+    }
 
     // Process file
-    process(ast, command, &mut content)?;
+    let original = document.source().to_string();
+
+    if let Some(output) = process(&mut document, command)? {
+        println!("{}", output);
+
+        return Ok(0)
+    }
 
     // Write output
+    let input = &document.options.file;
+    let content = document.source();
+
     if in_place {
-        file.seek(SeekFrom::Start(0))
-            .map_err(|err| format!("Unable to seek to the start of the input file: {}.", err))?;
-        
-        write!(file, "{}", content);
+        write_in_place(input, content, backup.as_deref())?;
+    }
 
-        // Resize file if needed
-        let file_metadata = file.metadata()
-                                .map_err(|err| format!("Unable to get output file metadata: {}.", err))?;
+    if diff {
+        print!("{}", diff::unified_diff(&original, content, &input.to_string_lossy(), 3));
 
-        if content.len() < file_metadata.len() as _ {
-            file.set_len(content.len() as _)
-                .map_err(|err| format!("Unable to resize input file after writing in-place: {}.", err))?;
+        if content != original {
+            return Ok(EXIT_CHANGED)
         }
-    } else {
+    } else if !in_place {
         println!("{}", content);
     }
 
     // Everything worked, return
-    Ok(())
+    Ok(0)
+}
+
+/// Return the exit code of the process when the given error happens.
+fn exit_code(err: &Error) -> i32 {
+    match *err {
+        Error::NotFound(_) => EXIT_NOT_FOUND,
+        Error::Ambiguous(_) => EXIT_AMBIGUOUS,
+        Error::Parse { .. } => EXIT_PARSE,
+        Error::InvalidValue(_) => EXIT_INVALID_VALUE,
+        Error::Io(_) => EXIT_IO,
+        Error::InvalidPath(_) => EXIT_INVALID_PATH,
+        Error::Eval(_) => EXIT_EVAL,
+        Error::Unsupported(_) => EXIT_UNSUPPORTED
+    }
+}
+
+/// Return the given error as a JSON object, for `--error-format json`.
+fn error_to_json(err: &Error) -> Json {
+    let mut fields = error_fields(err.kind(), err.message(), exit_code(err));
+
+    if let Error::Parse { line, column, .. } = *err {
+        fields.push(("line".to_string(), Json::Number(line.to_string())));
+        fields.push(("column".to_string(), Json::Number(column.to_string())));
+    }
+
+    Json::Object(fields)
 }
 
-fn process(mut ast: AST, command: Command, content: &mut String) -> Result<(), String> {
-    let root = &ast.arena[ast.root];
+/// Convert the given error in the command line arguments to JSON, like `error_to_json`.
+fn usage_error_to_json(err: &clap::Error) -> Json {
+    // Only keep the description of the error on a single line, without the usage that follows it
+    let description = err.message.split("\n\n").next().unwrap_or("");
+    let message = description.lines().map(str::trim).collect::<Vec<_>>().join(" ");
+    let message = message.strip_prefix("error: ").unwrap_or(&message);
 
+    Json::Object(error_fields("invalid-arguments", message, EXIT_INVALID_ARGUMENTS))
+}
+
+/// Return the fields shared by all the errors printed as JSON.
+fn error_fields(kind: &str, message: &str, exit_code: i32) -> Vec<(String, Json)> {
+    vec![
+        ("kind".to_string(), Json::String(kind.to_string())),
+        ("message".to_string(), Json::String(message.to_string())),
+        ("exit_code".to_string(), Json::Number(exit_code.to_string()))
+    ]
+}
+
+/// Return the index of the file to which the given command should be applied, which is the
+/// file defining its path, or the file defining the longest prefix of the path if the value
+/// is inserted. The input file is used if no file defines it.
+fn defining_file(files: &[(PathBuf, String)], command: &Command, options: &Options) -> Result<usize, Error> {
+    let path = match *command {
+        Command::Apply { .. } => return Err(Error::InvalidValue("Scripts cannot be applied with --follow-imports.".to_string())),
+        _ => match command.path() {
+            Some(path) => Path::parse(path)?,
+            None => return Ok(0)
+        }
+    };
+    let insert = matches!(*command, Command::Set { .. });
+
+    imports::defining_file(files, &path, insert, options)
+}
+
+/// Atomically replace the content of the given file by writing it to a temporary file
+/// with the same permissions and ownership, and then renaming it. If a backup suffix is
+/// given, the previous version of the file is kept next to it.
+fn write_in_place(path: &FilePath, content: &str, backup: Option<&str>) -> Result<(), Error> {
+    // Write to the target of symbolic links rather than replacing them
+    let path = fs::canonicalize(path)
+        .map_err(|err| Error::Io(format!("Unable to resolve path of input file: {}.", err)))?;
+    let metadata = fs::metadata(&path)
+        .map_err(|err| Error::Io(format!("Unable to get input file metadata: {}.", err)))?;
+
+    let file_name = path.file_name().unwrap().to_string_lossy().into_owned();
+    let tmp_path = path.with_file_name(format!(".{}.nixcfg-{}", file_name, process::id()));
+
+    let result = (|| {
+        let mut tmp = OpenOptions::new().write(true).create_new(true).open(&tmp_path)
+            .map_err(|err| Error::Io(format!("Unable to create temporary file '{}': {}.", tmp_path.display(), err)))?;
+
+        tmp.write_all(content.as_bytes())
+           .map_err(|err| Error::Io(format!("Unable to write output file: {}.", err)))?;
+        tmp.set_permissions(metadata.permissions())
+           .map_err(|err| Error::Io(format!("Unable to preserve permissions of input file: {}.", err)))?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{fchown, MetadataExt};
+
+            let tmp_metadata = tmp.metadata()
+                                  .map_err(|err| Error::Io(format!("Unable to get output file metadata: {}.", err)))?;
+
+            // Only root can give files away, so users allowed to write files they do not own
+            // (such as group-writable ones) end up owning the new file
+            if tmp_metadata.uid() != metadata.uid() || tmp_metadata.gid() != metadata.gid() {
+                match fchown(&tmp, Some(metadata.uid()), Some(metadata.gid())) {
+                    Err(ref err) if err.kind() == ErrorKind::PermissionDenied => (),
+                    result => result.map_err(|err| Error::Io(format!("Unable to preserve ownership of input file: {}.", err)))?
+                }
+            }
+        }
+
+        tmp.sync_all()
+           .map_err(|err| Error::Io(format!("Unable to write output file: {}.", err)))?;
+
+        if let Some(suffix) = backup {
+            let backup_path = path.with_file_name(format!("{}{}", file_name, suffix));
+
+            fs::copy(&path, &backup_path)
+                .map_err(|err| Error::Io(format!("Unable to create backup '{}': {}.", backup_path.display(), err)))?;
+        }
+
+        fs::rename(&tmp_path, &path)
+            .map_err(|err| Error::Io(format!("Unable to replace input file: {}.", err)))
+    })();
+
+    if result.is_err() {
+        // Do not leave the temporary file behind; the original file is untouched anyway
+        let _ = fs::remove_file(&tmp_path);
+    }
+
+    result
+}
+
+/// Run the given command on the document, and return its output if it is a query.
+fn process(document: &mut Document, command: Command) -> Result<Option<String>, Error> {
     match command {
-        Command::Get { path } => {
-            let parts: Vec<_> = path.split('.').collect();
-            let node = find_node(&ast, root, &parts, 0)?;
-
-            // Since we individually display nodes, we have to set the
-            // matching node as root of the AST and then display it whole
-            ast.root = node;
-
-            content.clear();
-            write!(content, "{}", ast);
-
-            // Trim output, since we may have some garbage
-            let trunc = content.as_bytes()
-                               .iter()
-                               .rev()
-                               .take_while(|ch| **ch == b' ' || **ch == b'\n')
-                               .count();
-            
-            if trunc > 0 {
-                let new_len = content.len() - trunc;
-
-                content.truncate(new_len)
+        Command::Get { path, output, locate, eval, nix_eval, nix_instantiate } => {
+            let path = Path::parse(&path)?;
+
+            if locate {
+                return Ok(Some(format_locations(document.locate(&path)?, output, &document.options.file)))
+            }
+
+            let mut values = if eval {
+                document.evaluate(&path)?
+            } else if nix_eval {
+                document.nix_evaluate(&path, &nix_instantiate)?
+            } else {
+                document.get_all(&path)?
+            };
+
+            if path.has_wildcards() || values.len() > 1 {
+                // Several values may be matched, which are displayed alongside their path
+                return format_values(values, output).map(Some)
+            }
+
+            let value = values.swap_remove(0);
+
+            match output {
+                OutputFormat::Nix => Ok(Some(value.source)),
+                OutputFormat::Json => Ok(Some(value.to_json()?.to_string()))
             }
         },
 
-        Command::Set { path, value, keep_eol } => {
+        Command::Set { path, value, keep_eol, json } => {
             let value = match value {
                 Some(value) => value,
                 None => {
                     let mut input = String::new();
 
                     stdin().read_to_string(&mut input)
-                           .map_err(|err| format!("Could not read replacement value from stdin: {}.", err))?;
+                           .map_err(|err| Error::Io(format!("Could not read replacement value from stdin: {}.", err)))?;
                     
                     // Trim end of line (unless the user asked us to keep it)
                     let input_len = input.len();
@@ -160,100 +606,224 @@ fn process(mut ast: AST, command: Command, content: &mut String) -> Result<(), S
                     input
                 }
             };
-            let parts: Vec<_> = path.split('.').collect();
+            let path = Path::parse(&path)?;
 
-            match find_node(&ast, root, &parts, 0) {
-                Ok(node) => {
-                    // We found a match, and we have to replace it
-                    let node = &ast.arena[node];
-                    let range = node.span.start as usize .. node.span.end.unwrap() as usize;
-                    
-                    content.replace_range(range, &value);
-                },
+            if json {
+                document.set_json(&path, &Json::parse(&value)?)?;
+            } else {
+                document.set(&path, &value)?;
+            }
 
-                Err(_) => {
-                    // We did not find a match, so we'll try to add the value ourselves
-                    panic!("not implemented")
-                }
+            Ok(None)
+        },
+
+        Command::Delete { path, prune } => {
+            document.delete(&Path::parse(&path)?, prune)?;
+
+            Ok(None)
+        },
+
+        Command::List { command } => process_list(document, command),
+
+        Command::Paths { path, values, output } => {
+            let path = match path {
+                Some(ref path) => Some(Path::parse(path)?),
+                None => None
+            };
+            let mut definitions = document.paths(path.as_ref())?;
+
+            if values {
+                return format_values(definitions, output).map(Some)
+            }
+
+            // Values defined several times are only listed once
+            definitions.dedup_by(|a, b| a.path == b.path);
+
+            let paths = definitions.into_iter().map(|value| value.path);
+            let result = match output {
+                OutputFormat::Nix => paths.collect::<Vec<_>>().join("\n"),
+                OutputFormat::Json => Json::Array(paths.map(Json::String).collect()).to_string()
+            };
+
+            Ok(Some(result))
+        },
+
+        Command::Apply { script } => {
+            let mut input = String::new();
+
+            match script {
+                Some(ref path) if path.to_str() != Some("-") => File::open(path)
+                    .and_then(|mut file| file.read_to_string(&mut input))
+                    .map_err(|err| Error::Io(format!("Unable to read script '{}': {}.", path.display(), err)))?,
+
+                _ => stdin().read_to_string(&mut input)
+                            .map_err(|err| Error::Io(format!("Could not read script from stdin: {}.", err)))?
+            };
+
+            // The results of queries are only printed once every command has succeeded
+            for output in apply(document, &input)? {
+                println!("{}", output);
             }
+
+            Ok(None)
         }
     }
-
-    Ok(())
 }
 
-fn find_node(ast: &AST, node: &ASTNode, parts: &[&str], i: usize) -> Result<NodeId, String> {
-    let part = parts[i];
-
-    /// Try to match the i'th child with the given path. On success, the ID of the r'th child
-    /// will be returned.
-    macro_rules! try_match {
-        ( $i: expr => $r: expr ) => ({
-            let ident_node = &ast.arena[node.children(&ast.arena).nth($i).unwrap()];
-            let j = try_advance_ident(ast, ident_node, parts, i);
-
-            if j > i {
-                // We did advance, which means we might have a match
-                let res_id = node.children(&ast.arena).nth($r).unwrap();
-
-                if j == parts.len() {
-                    // We even got to the end of the path, which means we have a complete match!
-                    Ok(res_id)
-                } else {
-                    // We're not at the end of the path, so we continue recursively
-                    find_node(ast, &ast.arena[res_id], parts, j)
-                }
-            } else {
-                node.children(&ast.arena)
-                    .filter_map(|id| find_node(ast, &ast.arena[id], parts, i).ok())
-                    .nth(0)
-                    .ok_or_else(|| format!("Part '{}' of path not found.", part))
+/// Format the given values alongside their path, either one per line or as a JSON object.
+fn format_values(values: Vec<Value>, output: OutputFormat) -> Result<String, Error> {
+    // The root of the file (matched by `**`) has no path, and its values are already listed
+    let values = values.into_iter().filter(|value| !value.path.is_empty());
+
+    match output {
+        OutputFormat::Nix => Ok(values.map(|value| format!("{} = {}", value.path, value.source))
+                                      .collect::<Vec<_>>()
+                                      .join("\n")),
+
+        OutputFormat::Json => {
+            let mut entries = Vec::new();
+
+            for value in values {
+                entries.push((value.path.clone(), value.to_json()?));
             }
-        });
-    }
 
-    match node.kind {
-        ASTKind::Apply => try_match!(0 => 1),
-        ASTKind::SetEntry => try_match!(0 => 2),
+            Ok(Json::Object(entries).to_string())
+        }
+    }
+}
 
-        // Try recursively on children
-        _ => node.children(&ast.arena)
-                 .filter_map(|id| find_node(ast, &ast.arena[id], parts, i).ok())
-                 .nth(0)
-                 .ok_or_else(|| format!("Part '{}' of path not found.", part))
+/// Format the given locations in the given file, either one per line or as a JSON array.
+fn format_locations(locations: Vec<Location>, output: OutputFormat, file: &FilePath) -> String {
+    let file = file.to_string_lossy();
+
+    match output {
+        OutputFormat::Nix => locations.into_iter()
+            .map(|Location { path, line, column, key, value }| match key {
+                Some(key) => format!("{}:{}:{}: {} (key {:?}, value {:?})", file, line, column, path, key, value),
+                None => format!("{}:{}:{}: {} (value {:?})", file, line, column, path, value)
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+
+        OutputFormat::Json => {
+            let span = |range: Range<usize>| Json::Object(vec![
+                ("start".to_string(), Json::Number(range.start.to_string())),
+                ("end".to_string(), Json::Number(range.end.to_string()))
+            ]);
+            let objects = locations.into_iter()
+                .map(|Location { path, line, column, key, value }| Json::Object(vec![
+                    ("file".to_string(), Json::String(file.to_string())),
+                    ("line".to_string(), Json::Number(line.to_string())),
+                    ("column".to_string(), Json::Number(column.to_string())),
+                    ("path".to_string(), Json::String(path)),
+                    ("key".to_string(), key.map(&span).unwrap_or(Json::Null)),
+                    ("value".to_string(), span(value))
+                ]))
+                .collect();
+
+            Json::Array(objects).to_string()
+        }
     }
 }
 
-fn try_advance_ident(ast: &AST, node: &ASTNode, parts: &[&str], i: usize) -> usize {
-    match node.kind {
-        ASTKind::Attribute | ASTKind::IndexSet => {
-            let mut j = i;
+/// Apply the commands of the given script to the document, and return the results of the
+/// queries it contains.
+fn apply(document: &mut Document, script: &str) -> Result<Vec<String>, Error> {
+    let mut outputs = Vec::new();
 
-            for sub_node in node.children(&ast.arena) {
-                let part = match parts.get(j) {
-                    Some(part) => part,
-                    None => return i
-                };
+    for (i, line) in script.lines().enumerate() {
+        let line = line.trim();
 
-                match &ast.arena[sub_node].data {
-                    &ASTData::Ident(_, ref ident) => if ident == part {
-                        j += 1
-                    },
-                    &ASTData::Token(_, TokenKind::Dot) => (),
+        if line.is_empty() || line.starts_with('#') {
+            continue
+        }
+
+        let fail = |err: Error| err.map_message(|message| format!("Line {}: {}", i + 1, message));
+        let words = split_words(line).map_err(|err| fail(Error::InvalidValue(err)))?;
+
+        // Parse the line exactly like command line arguments
+        let matches = Command::clap()
+            .setting(AppSettings::NoBinaryName)
+            .setting(AppSettings::InferSubcommands)
+            .get_matches_from_safe(words)
+            .map_err(|err| fail(Error::InvalidValue(err.message.lines().next().unwrap_or("").trim_start_matches("error: ").to_string())))?;
+
+        let command = match Command::from_clap(&matches) {
+            Command::Apply { .. } => return Err(fail(Error::InvalidValue("Scripts cannot apply other scripts.".to_string()))),
+            Command::Set { value: None, .. } => return Err(fail(Error::InvalidValue("Values must be given explicitly in scripts.".to_string()))),
+            command => command
+        };
+
+        outputs.extend(process(document, command).map_err(&fail)?);
+    }
 
-                    data => panic!("Unexpected data: {:?}", data)
+    Ok(outputs)
+}
+
+/// Split the given line into words like a shell would, handling quotes and escapes.
+fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word = None;
+    let mut chars = line.chars();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            ' ' | '\t' => if let Some(word) = word.take() {
+                words.push(word);
+            },
+
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(ch) => word.push(ch),
+                        None => return Err("Unterminated single quote.".to_string())
+                    }
                 }
-            }
+            },
+
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(ch) if ch == '"' || ch == '\\' || ch == '$' || ch == '`' => word.push(ch),
+                            Some(ch) => { word.push('\\'); word.push(ch); },
+                            None => return Err("Unterminated double quote.".to_string())
+                        },
+                        Some(ch) => word.push(ch),
+                        None => return Err("Unterminated double quote.".to_string())
+                    }
+                }
+            },
 
-            j
+            '\\' => match chars.next() {
+                Some(ch) => word.get_or_insert_with(String::new).push(ch),
+                None => return Err("Unterminated escape sequence.".to_string())
+            },
+
+            ch => word.get_or_insert_with(String::new).push(ch)
         }
+    }
+
+    words.extend(word);
+
+    Ok(words)
+}
+
 
-        // Try recursively on children
-        _ => node.children(&ast.arena)
-                 .map(|id| try_advance_ident(ast, &ast.arena[id], parts, i))
-                 .find(|j| *j > i)
-                 .unwrap_or(i)
+fn process_list(document: &mut Document, command: ListCommand) -> Result<Option<String>, Error> {
+    match command {
+        ListCommand::Add { path, values, prepend, unique } => document.list_add(&Path::parse(&path)?, &values, prepend, unique)?,
+        ListCommand::Remove { path, values } => document.list_remove(&Path::parse(&path)?, &values)?,
+        ListCommand::Contains { path, value } => return Ok(Some(document.list_contains(&Path::parse(&path)?, &value)?.to_string()))
     }
+
+    Ok(None)
 }
 
 
@@ -264,13 +834,35 @@ mod tests {
     use std::fs;
     use std::path::PathBuf;
 
+    /// Run the given command on the given content, and return the output of the query or
+    /// the resulting content.
+    fn run_command(content: &str, command: Command, options: &Options) -> Result<String, String> {
+        let mut document = Document::parse(content.to_string()).map_err(|err| err.to_string())?;
+
+        document.options = options.clone();
+
+        match process(&mut document, command) {
+            Ok(output) => Ok(output.unwrap_or_else(|| document.into_source())),
+            Err(err) => Err(err.to_string())
+        }
+    }
+
     fn assert_value_eq(content: &str, path: &str, expected: &str) {
-        let mut output = content.to_string();
+        assert_get_eq(content, path, &Options::default(), Ok(expected))
+    }
 
-        let result = process(rnix::parse(content).unwrap(),
-                             Command::Get { path: path.to_string() }, &mut output);
+    fn assert_get_eq(content: &str, path: &str, options: &Options, expected: Result<&str, &str>) {
+        let result = run_command(content, Command::Get { path: path.to_string(), output: OutputFormat::Nix, locate: false, eval: false, nix_eval: false, nix_instantiate: String::new() }, options);
         
-        assert_eq!(result.map(|_| output.as_str()), Ok(expected))
+        assert_eq!(result.as_ref().map(String::as_str).map_err(String::as_str), expected)
+    }
+
+    fn assert_set_eq(content: &str, path: &str, value: &str, expected: &str) {
+        let command = Command::Set { path: path.to_string(), value: Some(value.to_string()), keep_eol: false, json: false };
+
+        let result = run_command(content, command, &Options::default());
+
+        assert_eq!(result.as_ref().map(String::as_str), Ok(expected))
     }
 
     #[test]
@@ -287,10 +879,589 @@ mod tests {
           stdenv.mkDerivation { name = "foo"; buildInputs = [ latest.rustChannels.nightly.rust ]; }
         "#;
 
-        assert_value_eq(nix, "nixpkgs-mozilla", "fetchTarball https://github.com/mozilla/nixpkgs-mozilla/archive/master.tar.gz");
-        assert_value_eq(nix, "overlays", "[ (import nixpkgs-mozilla) ]");
-        assert_value_eq(nix, "stdenv.mkDerivation", r#"{ name = "foo"; buildInputs = [ latest.rustChannels.nightly.rust ]; }"#);
-        assert_value_eq(nix, "stdenv.mkDerivation.name", "\"foo\"");
+        let deep = Options { deep: true, ..Options::default() };
+
+        assert_get_eq(nix, "nixpkgs-mozilla", &deep, Ok("fetchTarball https://github.com/mozilla/nixpkgs-mozilla/archive/master.tar.gz"));
+        assert_get_eq(nix, "overlays", &deep, Ok("[ (import nixpkgs-mozilla) ]"));
+        assert_get_eq(nix, "stdenv.mkDerivation", &deep, Ok(r#"{ name = "foo"; buildInputs = [ latest.rustChannels.nightly.rust ]; }"#));
+        assert_get_eq(nix, "stdenv.mkDerivation.name", &deep, Ok("\"foo\""));
+
+        assert_value_eq(nix, "let.nixpkgs-mozilla", "fetchTarball https://github.com/mozilla/nixpkgs-mozilla/archive/master.tar.gz");
+        assert_get_eq(nix, "overlays", &Options::default(), Err("No value matches path 'overlays'."));
+    }
+
+    #[test]
+    fn test_strict_paths() {
+        let nix = r#"{ pkgs, ... }:
+
+          let
+            name = "unrelated";
+            user = { name = "alice"; };
+          in
+
+          with pkgs; {
+            networking.hostName = "paradise";
+            environment.systemPackages = [ (writeScriptBin "hello" { name = "hello"; }) ];
+          }
+        "#;
+        let deep = Options { deep: true, ..Options::default() };
+
+        assert_get_eq(nix, "name", &Options::default(), Err("No value matches path 'name'."));
+        assert_get_eq(nix, "user", &deep, Ok("{ name = \"alice\"; }"));
+        assert_get_eq(nix, "hostName", &Options::default(), Err("No value matches path 'hostName'."));
+        assert_value_eq(nix, "networking.hostName", "\"paradise\"");
+        assert_value_eq(nix, "let.user.name", "\"alice\"");
+        assert_value_eq(nix, "let.*", "let.name = \"unrelated\"\nlet.user = { name = \"alice\"; }");
+
+        assert_set_eq(nix, "networking.domain", "\"local\"",
+                      &nix.replace("\"paradise\";", "\"paradise\";\n            networking.domain = \"local\";"));
+        assert_set_eq(nix, "let.name", "\"other\"", &nix.replace("unrelated", "other"));
+    }
+
+    #[test]
+    fn test_merged_paths() {
+        let nix = r#"
+          {
+            networking.firewall.enable = true;
+            networking.firewall.allowedTCPPorts = [ 80 8080 ];
+            networking.hostName = "paradise";
+            networking = { firewall.allowPing = false; };
+          }
+        "#;
+
+        assert_value_eq(nix, "networking.firewall", "{ enable = true; allowedTCPPorts = [ 80 8080 ]; allowPing = false; }");
+        assert_value_eq(nix, "networking.firewall.enable", "true");
+        assert_value_eq("{ a = { b = 1; }; a.c = 2; }", "a", "{ b = 1; c = 2; }");
+        assert_value_eq("{ a = { b = 1; }; }", "a", "{ b = 1; }");
+    }
+
+    #[test]
+    fn test_formatting() {
+        let nix = r#"
+          {
+            services.nginx = {
+              enable = true; # Serve the site
+              /* Ports */
+              ports = [
+                80
+                443
+              ];
+            };
+            packages =
+              [ pkgs.hello ];
+            script = ''
+              echo hello
+          '';
+          }
+        "#;
+
+        assert_value_eq(nix, "services.nginx", "{\n  enable = true; # Serve the site\n  /* Ports */\n  ports = [\n    80\n    443\n  ];\n}");
+        assert_value_eq(nix, "services.nginx.ports", "[\n  80\n  443\n]");
+        assert_value_eq(nix, "packages", "[ pkgs.hello ]");
+        assert_value_eq(nix, "script", "''\n              echo hello\n          ''");
+        assert_value_eq(nix, "services.*", "services.nginx = {\n  enable = true; # Serve the site\n  /* Ports */\n  ports = [\n    80\n    443\n  ];\n}");
+
+        // Indentation within strings is part of their value
+        assert_value_eq("{\n  a = [\n    \"foo\n    bar\"\n  ];\n}", "a", "[\n  \"foo\n    bar\"\n]");
+        assert_value_eq("{\n\u{a0}\u{a0}a = [\n\u{a0}\u{a0}\u{a0}1\n\u{a0}];\n}", "a", "[\n\u{a0}\u{a0}1\n]");
+        assert_value_eq("{ a = /* c\n\u{a0}*/ ''\n\u{a0}x''; }", "a", "''\n\u{a0}x''");
+    }
+
+    #[test]
+    fn test_quoted_paths() {
+        let nix = r#"
+          {
+            services."nginx".virtualHosts."example.com".root = "/var/www";
+            "foo.bar" = 1;
+            foo.bar = 2;
+            ${name} = 3;
+            x.${name} = 4;
+          }
+        "#;
+
+        assert_value_eq(nix, r#"services.nginx.virtualHosts."example.com".root"#, "\"/var/www\"");
+        assert_value_eq(nix, r#"services."nginx".virtualHosts"#, r#"{ "example.com".root = "/var/www"; }"#);
+        assert_value_eq(nix, r#""foo.bar""#, "1");
+        assert_value_eq(nix, "foo.bar", "2");
+        assert_value_eq(nix, "x", "{ ${name} = 4; }");
+
+        let command = Command::Get { path: "name".to_string(), output: OutputFormat::Nix, locate: false, eval: false, nix_eval: false, nix_instantiate: String::new() };
+
+        assert_eq!(run_command(nix, command, &Options::default()),
+                   Err("No value matches path 'name'. The attribute name '${name}' at 6:13 is computed at runtime, \
+                        and cannot be resolved.".to_string()));
+        assert_get_eq(nix, "x.name", &Options::default(),
+                      Err("No value matches path 'x.name'. The attribute name '${name}' at 7:15 is computed at runtime, \
+                           and cannot be resolved."));
+
+        // Names computed at runtime elsewhere cannot hide the value
+        assert_get_eq(nix, "foo.baz", &Options::default(), Err("No value matches path 'foo.baz'."));
+        assert_get_eq(nix, "foo[0]", &Options::default(), Err("No value matches path 'foo[0]'."));
+        assert_get_eq(nix, "let.x", &Options::default(), Err("No value matches path 'let.x'."));
+
+        assert_set_eq("{ a = 1; }", r#"b."c.d""#, "2", r#"{ a = 1; b."c.d" = 2; }"#);
+        assert_set_eq(r#"{ "a b" = 1; }"#, r#""a b""#, "2", r#"{ "a b" = 2; }"#);
+    }
+
+    #[test]
+    fn test_selectors() {
+        let nix = r#"{ config, ... }: {
+  imports = [ ./a.nix ./b.nix ];
+  fileSystems = [ { device = "/dev/sda1"; } { device = "/dev/sda2"; fsType = "ext4"; } ];
+  users.users.alice = { isNormalUser = true; home = "/home/alice"; };
+  users.users.root.isNormalUser = false;
+  users.users.bob.isNormalUser = true;
+}"#;
+
+        assert_value_eq(nix, "imports[0]", "./a.nix");
+        assert_value_eq(nix, "imports[-1]", "./b.nix");
+        assert_value_eq(nix, "imports[*]", "imports[0] = ./a.nix\nimports[1] = ./b.nix");
+        assert_value_eq(nix, "fileSystems[1].device", "\"/dev/sda2\"");
+        assert_value_eq(nix, "fileSystems[fsType == \"ext4\"].device", "fileSystems[1].device = \"/dev/sda2\"");
+        assert_value_eq(nix, "users.users.*[isNormalUser == true]",
+                        "users.users.alice = { isNormalUser = true; home = \"/home/alice\"; }\nusers.users.bob = { isNormalUser = true; }");
+        assert_value_eq(nix, "users.users.*[isNormalUser != true].isNormalUser", "users.users.root.isNormalUser = false");
+
+        let command = Command::Get { path: "imports[2]".to_string(), output: OutputFormat::Nix, locate: false, eval: false, nix_eval: false, nix_instantiate: String::new() };
+
+        assert_eq!(run_command(nix, command, &Options::default()), Err("No value matches path 'imports[2]'.".to_string()));
+
+        assert_set_eq("{ a = [ 1 2 3 ]; }", "a[1]", "4", "{ a = [ 1 4 3 ]; }");
+        assert_set_eq("{ a.x.b = 1; a.y.b = 2; }", "a.*.b", "3", "{ a.x.b = 3; a.y.b = 3; }");
+
+        let command = Command::Delete { path: "a[-1]".to_string(), prune: false };
+
+        assert_eq!(run_command("{ a = [ 1 2 3 ]; b = 1; }", command, &Options::default()), Ok("{ a = [ 1 2 ]; b = 1; }".to_string()));
+
+        let command = ListCommand::Add { path: "a[0].b".to_string(), values: vec!["1".to_string()], prepend: false, unique: false };
+
+        assert_eq!(run_command("{ a = [ { b = [ ]; } ]; }", Command::List { command }, &Options::default()),
+                   Ok("{ a = [ { b = [ 1 ]; } ]; }".to_string()));
+    }
+
+    #[test]
+    fn test_wildcards() {
+        let nix = r#"{
+  services.nginx.enable = true;
+  services.sshd = { enable = false; ports = [ 22 ]; };
+  services.tor.client.enable = true;
+  hardware.bluetooth.enable = true;
+}"#;
+
+        assert_value_eq(nix, "services.*.enable", "services.nginx.enable = true\nservices.sshd.enable = false");
+        assert_value_eq(nix, "**.enable", "services.nginx.enable = true\nservices.sshd.enable = false\n\
+                                           services.tor.client.enable = true\nhardware.bluetooth.enable = true");
+        assert_value_eq(nix, "services.**[enable == true]", "services.nginx = { enable = true; }\nservices.tor.client = { enable = true; }");
+        assert_value_eq(nix, "services.sshd.**", "services.sshd = { enable = false; ports = [ 22 ]; }\n\
+                                                  services.sshd.enable = false\nservices.sshd.ports = [ 22 ]\nservices.sshd.ports[0] = 22");
+
+        let command = Command::Get { path: "**.enable".to_string(), output: OutputFormat::Json, locate: false, eval: false, nix_eval: false, nix_instantiate: String::new() };
+
+        assert_eq!(run_command(nix, command, &Options::default()).unwrap(), r#"{"services.nginx.enable":true,"services.sshd.enable":false,"services.tor.client.enable":true,"hardware.bluetooth.enable":true}"#);
+
+        assert_set_eq(nix, "**.enable", "false", &nix.replace("true", "false"));
+
+        let command = Command::Get { path: "**".to_string(), output: OutputFormat::Json, locate: false, eval: false, nix_eval: false, nix_instantiate: String::new() };
+
+        assert_value_eq("{ a = 1; }", "**", "a = 1");
+        assert_eq!(run_command("{ a = 1; }", command, &Options::default()).unwrap(), r#"{"a":1}"#);
+    }
+
+    #[test]
+    fn test_paths() {
+        fn assert_paths_eq(content: &str, path: Option<&str>, values: bool, output: OutputFormat, expected: Result<&str, &str>) {
+            let command = Command::Paths { path: path.map(str::to_string), values, output };
+            let result = run_command(content, command, &Options::default());
+
+            assert_eq!(result.as_ref().map(String::as_str).map_err(String::as_str), expected);
+        }
+
+        let nix = r#"{ config, ... }:
+
+          {
+            networking.hostName = "paradise";
+            networking.firewall = { enable = true; allowedTCPPorts = [ 80 ]; };
+            users.users.alice = { isNormalUser = true; extraGroups = [ "wheel" ]; };
+            environment = mkMerge [ { variables = { }; } (mkIf config.x { x = 1; }) (mkIf config.y { x = 2; }) ];
+          }
+        "#;
+
+        assert_paths_eq(nix, None, false, OutputFormat::Nix, Ok("networking.hostName\nnetworking.firewall.enable\n\
+                                                                 networking.firewall.allowedTCPPorts\nusers.users.alice.isNormalUser\n\
+                                                                 users.users.alice.extraGroups\nenvironment.variables\nenvironment.x"));
+        assert_paths_eq(nix, Some("networking"), true, OutputFormat::Nix,
+                        Ok("networking.hostName = \"paradise\"\nnetworking.firewall.enable = true\nnetworking.firewall.allowedTCPPorts = [ 80 ]"));
+        assert_paths_eq(nix, Some("environment.x"), true, OutputFormat::Nix, Ok("environment.x = 1\nenvironment.x = 2"));
+        assert_paths_eq(nix, Some("users"), false, OutputFormat::Json,
+                        Ok(r#"["users.users.alice.isNormalUser","users.users.alice.extraGroups"]"#));
+        assert_paths_eq(nix, Some("users.*.*"), true, OutputFormat::Json,
+                        Ok(r#"{"users.users.alice.isNormalUser":true,"users.users.alice.extraGroups":["wheel"]}"#));
+        assert_paths_eq(nix, Some("services"), false, OutputFormat::Nix, Err("No value matches path 'services'."));
+        assert_paths_eq("[ 1 ]", None, false, OutputFormat::Nix, Ok(""));
+    }
+
+    #[test]
+    fn test_ambiguity() {
+        fn assert_set_with_eq(content: &str, path: &str, selection: Selection, expected: Result<&str, &str>) {
+            let command = Command::Set { path: path.to_string(), value: Some("3".to_string()), keep_eol: false, json: false };
+            let options = Options { selection, ..Options::default() };
+            let result = run_command(content, command, &options);
+
+            assert_eq!(result.as_ref().map(String::as_str).map_err(String::as_str), expected)
+        }
+
+        let nix = "{\n  a.b = 1;\n  a = { b = 2; };\n}";
+
+        assert_set_with_eq(nix, "a.b", Selection::Unique,
+                           Err("Path 'a.b' is defined 2 times (at 2:3, 3:9); use --all, --first or --nth to choose which definitions to use."));
+        assert_set_with_eq(nix, "a.b", Selection::First, Ok("{\n  a.b = 3;\n  a = { b = 2; };\n}"));
+        assert_set_with_eq(nix, "a.b", Selection::Nth(2), Ok("{\n  a.b = 1;\n  a = { b = 3; };\n}"));
+        assert_set_with_eq(nix, "a.b", Selection::Nth(3), Err("Path 'a.b' is only defined 2 times."));
+        assert_set_with_eq(nix, "a.b", Selection::All, Ok("{\n  a.b = 3;\n  a = { b = 3; };\n}"));
+        assert_get_eq(nix, "a.b", &Options { selection: Selection::All, ..Options::default() }, Ok("a.b = 1\na.b = 2"));
+        assert_get_eq(nix, "a.b", &Options { selection: Selection::Nth(2), ..Options::default() }, Ok("2"));
+
+        let nix = "{ config = mkMerge [ (mkIf a { x = 1; y = 2; }) (mkIf b { x = 3; }) ]; }";
+
+        assert_get_eq(nix, "config.y", &Options::default(), Ok("2"));
+        assert_get_eq(nix, "config.x", &Options::default(),
+                      Err("Path 'config.x' is defined 2 times (at 1:32, 1:59); use --all, --first or --nth to choose which definitions to use."));
+        assert_get_eq(nix, "config.x", &Options { deep: true, ..Options::default() },
+                      Err("Path 'config.x' is defined 2 times (at 1:32, 1:59); use --all, --first or --nth to choose which definitions to use."));
+
+        // Attribute sets defined several times are merged
+        assert_value_eq("{ a = { b = 1; }; a = { c = 2; }; }", "a", "{ b = 1; c = 2; }");
+    }
+
+    #[test]
+    fn test_locate() {
+        fn assert_locate_eq(content: &str, path: &str, output: OutputFormat, selection: Selection, expected: &str) {
+            let command = Command::Get { path: path.to_string(), output, locate: true, eval: false,
+                                         nix_eval: false, nix_instantiate: String::new() };
+            let options = Options { file: PathBuf::from("file.nix"), selection, ..Options::default() };
+
+            assert_eq!(run_command(content, command, &options).unwrap(), expected);
+        }
+
+        let nix = "{\n  a.b = 1;\n  a = { b = 2; c = [ 3 ]; };\n  inherit d;\n}";
+
+        assert_locate_eq(nix, "a.b", OutputFormat::Nix, Selection::Unique,
+                         "file.nix:2:3: a.b (key 4..7, value 10..11)\nfile.nix:3:9: a.b (key 21..22, value 25..26)");
+        assert_locate_eq(nix, "a.b", OutputFormat::Nix, Selection::Nth(2), "file.nix:3:9: a.b (key 21..22, value 25..26)");
+        assert_locate_eq(nix, "a", OutputFormat::Nix, Selection::Unique,
+                         "file.nix:2:3: a.b (key 4..7, value 10..11)\nfile.nix:3:3: a (key 15..16, value 19..40)");
+        assert_locate_eq(nix, "a.c[0]", OutputFormat::Nix, Selection::Unique, "file.nix:3:22: a.c[0] (value 34..35)");
+        assert_locate_eq(nix, "d", OutputFormat::Nix, Selection::Unique, "file.nix:4:11: d (key 52..53, value 52..53)");
+        assert_locate_eq(nix, "a.c", OutputFormat::Json, Selection::Unique,
+                         r#"[{"file":"file.nix","line":3,"column":16,"path":"a.c","key":{"start":28,"end":29},"value":{"start":32,"end":37}}]"#);
+    }
+
+    #[test]
+    fn test_insert() {
+        assert_set_eq("{ a = { b = 1; }; }", "a.c", "2", "{ a = { b = 1; c = 2; }; }");
+        assert_set_eq("{ a = { }; }", "a.b.c", "true", "{ a = { b.c = true; }; }");
+        assert_set_eq("{ x }: {\n  a = 1;\n}", "b", "2", "{ x }: {\n  a = 1;\n  b = 2;\n}");
+        assert_set_eq("let x = 1; in {\n}", "a", "x", "let x = 1; in {\n  a = x;\n}");
+        assert_set_eq("{\n  a = {\n    b = 1;\n  };\n}", "a.c", "[ ]", "{\n  a = {\n    b = 1;\n    c = [ ];\n  };\n}");
+        assert_set_eq("{\n  a = 1; # One.\n}", "b", "2", "{\n  a = 1; # One.\n  b = 2;\n}");
+        assert_set_eq("{\r\n  a = 1;\r\n}", "b", "2", "{\r\n  a = 1;\r\n  b = 2;\r\n}");
+        assert_set_eq("{\r\n  a = {\r\n  };\r\n}", "a.b", "2", "{\r\n  a = {\r\n    b = 2;\r\n  };\r\n}");
+
+        let command = Command::Set { path: "a.b".to_string(), value: Some("2".to_string()), keep_eol: false, json: false };
+
+        assert!(run_command("{ a = 1; }", command, &Options::default()).is_err());
+    }
+
+    #[test]
+    fn test_set_json() {
+        fn assert_set_json_eq(content: &str, path: &str, value: &str, expected: &str) {
+            let command = Command::Set { path: path.to_string(), value: Some(value.to_string()), keep_eol: false, json: true };
+
+            let result = run_command(content, command, &Options::default());
+
+            assert_eq!(result.as_ref().map(String::as_str), Ok(expected))
+        }
+
+        assert_set_json_eq("{\n    a = 1;\n}", "a", r#""${x}""#, "{\n    a = \"\\${x}\";\n}");
+        assert_set_json_eq("{\n    a = 1;\n}", "a", r#"{"b": [1, 2]}"#, "{\n    a = {\n        b = [ 1 2 ];\n    };\n}");
+        assert_set_json_eq("{\n  a = 1;\n}", "b.c", r#"{"d": true}"#, "{\n  a = 1;\n  b.c = {\n    d = true;\n  };\n}");
+        assert_set_json_eq("{\n  a = {\n  };\n}", "a.b", r#"{"c": null}"#, "{\n  a = {\n    b = {\n      c = null;\n    };\n  };\n}");
+    }
+
+    #[test]
+    fn test_validation() {
+        let command = Command::Set { path: "a".to_string(), value: Some("{ b = ; }".to_string()), keep_eol: false, json: false };
+        let result = run_command("{ a = 1; }", command, &Options::default());
+
+        assert_eq!(result, Err("Unable to parse value at 1:7: expected an expression, found ';'.\n{ b = ; }\n      ^".to_string()));
+
+        let command = ListCommand::Add { path: "a".to_string(), values: vec!["-1".to_string()], prepend: false, unique: false };
+        let result = run_command("{ a = [ ]; }", Command::List { command }, &Options::default());
+
+        assert_eq!(result, Err("Unable to parse resulting file at 1:9: expected an expression, found '-'.\n{ a = [ -1 ]; }\n        ^".to_string()));
+
+        // Inherited names are not values that can be replaced
+        let command = Command::Set { path: "b.e".to_string(), value: Some("\"x\"".to_string()), keep_eol: false, json: false };
+        let result = run_command("{ b = { inherit (x) e f; }; }", command, &Options::default());
+
+        assert_eq!(result, Err("Path 'b.e' is inherited, and cannot be replaced.".to_string()));
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut document = Document::parse("{\n  a = 1;\n  b = [ ];\n}".to_string()).unwrap();
+        let script = r#"
+            # Comments and blank lines are ignored.
+            set a 2
+            get a
+
+            set c.d '"x y"'
+            list add b 1 "2"
+            delete a
+            g c
+        "#;
+
+        assert_eq!(apply(&mut document, script), Ok(vec!["2".to_string(), "{ d = \"x y\"; }".to_string()]));
+        assert_eq!(document.source(), "{\n  b = [ 1 2 ];\n  c.d = \"x y\";\n}");
+
+        let mut document = Document::parse("{ a = 1; }".to_string()).unwrap();
+
+        assert_eq!(apply(&mut document, "set a 2\ndelete b"), Err(Error::NotFound("Line 2: No value matches path 'b'.".to_string())));
+        assert_eq!(apply(&mut document, "set a"), Err(Error::InvalidValue("Line 1: Values must be given explicitly in scripts.".to_string())));
+        assert_eq!(apply(&mut document, "set a '1"), Err(Error::InvalidValue("Line 1: Unterminated single quote.".to_string())));
+        assert!(apply(&mut document, "frobnicate a").is_err());
+    }
+
+    #[test]
+    fn test_errors() {
+        let get = |content: &str, path: &str| {
+            let mut document = Document::parse(content.to_string())?;
+            let command = Command::Get { path: path.to_string(), output: OutputFormat::Nix, locate: false, eval: false,
+                                         nix_eval: false, nix_instantiate: String::new() };
+
+            process(&mut document, command)
+        };
+        let exit_code = |result: Result<Option<String>, Error>| exit_code(&result.unwrap_err());
+
+        assert_eq!(exit_code(get("{ a = 1; }", "b")), EXIT_NOT_FOUND);
+        assert_eq!(exit_code(get("{ a.b = 1; a = { b = 2; }; }", "a.b")), EXIT_AMBIGUOUS);
+        assert_eq!(exit_code(get("{ a = 1 }", "a")), EXIT_PARSE);
+        assert_eq!(exit_code(get("{ a = 1; }", "a..b")), EXIT_INVALID_PATH);
+        assert_eq!(exit_code(get("{ a = http/*//x.y; }", "a")), EXIT_PARSE);
+        assert_eq!(exit_code(Document::open(FilePath::new("/nonexistent/file.nix")).map(|_| None)), EXIT_IO);
+
+        assert_eq!(error_to_json(&get("{ a = 1; }", "b").unwrap_err()).to_string(),
+                   r#"{"kind":"not-found","message":"No value matches path 'b'.","exit_code":3}"#);
+        assert_eq!(error_to_json(&get("{\n  a = 1\n}", "a").unwrap_err()).to_string(),
+                   r#"{"kind":"parse","message":"Unable to parse input file at 3:1: expected ';', found '}'.\n}\n^","exit_code":5,"line":3,"column":1}"#);
+
+        // The error format is needed to report errors in the arguments themselves
+        let args = |args: &str| args.split(' ').map(String::from).collect::<Vec<_>>();
+
+        assert_eq!(requested_error_format(&args("nixcfg --error-format json get")), ErrorFormat::Json);
+        assert_eq!(requested_error_format(&args("nixcfg --error-format=json get")), ErrorFormat::Json);
+        assert_eq!(requested_error_format(&args("nixcfg --error-format=xml get")), ErrorFormat::Human);
+        assert_eq!(requested_error_format(&args("nixcfg get a")), ErrorFormat::Human);
+
+        let err = Args::clap().global_setting(AppSettings::ColorNever)
+                              .get_matches_from_safe(args("nixcfg --error-format json --foo get a"))
+                              .unwrap_err();
+
+        assert_eq!(usage_error_to_json(&err).to_string(),
+                   r#"{"kind":"invalid-arguments","message":"Found argument '--foo' which wasn't expected, or isn't valid in this context","exit_code":1}"#);
+
+        let err = Args::clap().global_setting(AppSettings::ColorNever)
+                              .get_matches_from_safe(args("nixcfg --error-format json get"))
+                              .unwrap_err();
+
+        assert_eq!(usage_error_to_json(&err).to_string(),
+                   r#"{"kind":"invalid-arguments","message":"The following required arguments were not provided: <path>","exit_code":1}"#);
+    }
+
+    #[test]
+    fn test_split_words() {
+        assert_eq!(split_words(r#"set  a\ b 'c "d"' "e \"$f\" \g"x"#),
+                   Ok(vec!["set".to_string(), "a b".to_string(), "c \"d\"".to_string(), "e \"$f\" \\gx".to_string()]));
+    }
+
+    #[test]
+    fn test_delete() {
+        fn assert_delete_eq(content: &str, path: &str, prune: bool, expected: &str) {
+            let command = Command::Delete { path: path.to_string(), prune };
+
+            let result = run_command(content, command, &Options::default());
+
+            assert_eq!(result.as_ref().map(String::as_str), Ok(expected))
+        }
+
+        assert_delete_eq("{ a = 1; b = 2; }", "a", false, "{ b = 2; }");
+        assert_delete_eq("{ a = 1; b = 2; }", "b", false, "{ a = 1; }");
+        assert_delete_eq("{\n  a = 1;\n\n  # About b.\n  b = 2; # Two.\n  c = 3;\n}", "b", false, "{\n  a = 1;\n\n  c = 3;\n}");
+        assert_delete_eq("{ a.b = 1; a.c = 2; d = 3; }", "a", false, "{ d = 3; }");
+        assert_delete_eq("{ a = { b = { c = 1; }; }; d = 3; }", "a.b.c", false, "{ a = { b = { }; }; d = 3; }");
+        assert_delete_eq("{ a = { b = { c = 1; }; }; d = 3; }", "a.b.c", true, "{ d = 3; }");
+        assert_delete_eq("{ a = { b = { c = 1; }; e = 2; }; }", "a.b.c", true, "{ a = { e = 2; }; }");
+        assert_delete_eq("{ b = { inherit (x) e f; }; }", "b.e", false, "{ b = { inherit (x) f; }; }");
+        assert_delete_eq("{ inherit c; d = 1; }", "c", false, "{ d = 1; }");
+    }
+
+    #[test]
+    fn test_list() {
+        fn assert_list_eq(content: &str, command: ListCommand, expected: Result<&str, &str>) {
+            let result = run_command(content, Command::List { command }, &Options::default());
+
+            assert_eq!(result.as_ref().map(String::as_str).map_err(String::as_str), expected)
+        }
+
+        fn add(path: &str, values: &[&str], prepend: bool, unique: bool) -> ListCommand {
+            ListCommand::Add { path: path.to_string(), values: values.iter().map(|v| v.to_string()).collect(), prepend, unique }
+        }
+
+        fn remove(path: &str, values: &[&str]) -> ListCommand {
+            ListCommand::Remove { path: path.to_string(), values: values.iter().map(|v| v.to_string()).collect() }
+        }
+
+        let inline = "{ a = with pkgs; [ git vim ]; }";
+        let multiline = "{\n  a = [\n    git\n    vim # Editor.\n  ];\n}";
+
+        assert_list_eq(inline, add("a", &["htop"], false, false), Ok("{ a = with pkgs; [ git vim htop ]; }"));
+        assert_list_eq(inline, add("a", &["htop", "tmux"], true, false), Ok("{ a = with pkgs; [ htop tmux git vim ]; }"));
+        assert_list_eq(inline, add("a", &["git"], false, true), Err("Value 'git' is already in the list."));
+        assert_list_eq(inline, remove("a", &["git"]), Ok("{ a = with pkgs; [ vim ]; }"));
+        assert_list_eq(inline, remove("a", &["htop"]), Err("Value 'htop' is not in the list."));
+        assert_list_eq("{ a = [ ]; }", add("a", &["1", "2"], false, false), Ok("{ a = [ 1 2 ]; }"));
+        assert_list_eq("{\n  a = [\n  ];\n}", add("a", &["1"], false, false), Ok("{\n  a = [\n    1\n  ];\n}"));
+        assert_list_eq("{ a = 1; }", add("a", &["1"], false, false), Err("Value at path 'a' is not a list."));
+        assert_list_eq("{ inherit (x) a; }", add("a", &["1"], false, false), Err("Path 'a' is inherited, and cannot be edited as a list."));
+        assert_list_eq("{ inherit (x) a; }", remove("a", &["1"]), Err("Path 'a' is inherited, and cannot be edited as a list."));
+
+        assert_list_eq(multiline, add("a", &["htop"], false, false), Ok("{\n  a = [\n    git\n    vim # Editor.\n    htop\n  ];\n}"));
+        assert_list_eq(multiline, remove("a", &["vim"]), Ok("{\n  a = [\n    git\n  ];\n}"));
+        assert_list_eq("{\r\n  a = [\r\n    1\r\n  ];\r\n}", add("a", &["2"], false, false), Ok("{\r\n  a = [\r\n    1\r\n    2\r\n  ];\r\n}"));
+        assert_list_eq("{\r\n  a = [\r\n    1\r\n  ];\r\n}", add("a", &["0"], true, false), Ok("{\r\n  a = [\r\n    0\r\n    1\r\n  ];\r\n}"));
+
+        assert_list_eq(inline, ListCommand::Contains { path: "a".to_string(), value: "vim".to_string() }, Ok("true"));
+        assert_list_eq(inline, ListCommand::Contains { path: "a".to_string(), value: "htop".to_string() }, Ok("false"));
+    }
+
+    #[test]
+    fn test_write_in_place() {
+        let dir = std::env::temp_dir().join(format!("nixcfg-test-{}", process::id()));
+        let path = dir.join("configuration.nix");
+
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&path, "{ a = 1; }").unwrap();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        }
+
+        write_in_place(&path, "{ a = 2; }", Some(".bak")).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{ a = 2; }");
+        assert_eq!(fs::read_to_string(dir.join("configuration.nix.bak")).unwrap(), "{ a = 1; }");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o640);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_follow_imports() {
+        let dir = std::env::temp_dir().join(format!("nixcfg-imports-{}", process::id()));
+        let root = dir.join("configuration.nix");
+
+        fs::create_dir_all(dir.join("services")).unwrap();
+        fs::write(&root, "{ imports = [ ./hardware.nix ./services <nixpkgs/modules> ]; networking.hostName = \"a\"; }").unwrap();
+        fs::write(dir.join("hardware.nix"), "{ ... }: { boot.loader.grub.enable = true; imports = [ ./configuration.nix ]; }").unwrap();
+        fs::write(dir.join("services/default.nix"), "{ imports = [ ./web.nix ]; services.sshd.enable = true; }").unwrap();
+        fs::write(dir.join("services/web.nix"), "{ services.nginx.enable = true; boot.loader.grub.enable = false; }").unwrap();
+
+        let files = imports::load_imports(&root, fs::read_to_string(&root).unwrap()).unwrap();
+        let names: Vec<_> = files.iter().map(|(path, _)| path.strip_prefix(&dir).unwrap().to_path_buf()).collect();
+
+        assert_eq!(names, vec![PathBuf::from("configuration.nix"), PathBuf::from("hardware.nix"),
+                               PathBuf::from("services/default.nix"), PathBuf::from("services/web.nix")]);
+
+        let defining_file = |command: Command| defining_file(&files, &command, &Options::default()).map_err(|err| err.to_string());
+        let get = |path: &str| Command::Get { path: path.to_string(), output: OutputFormat::Nix, locate: false, eval: false, nix_eval: false, nix_instantiate: String::new() };
+        let set = |path: &str| Command::Set { path: path.to_string(), value: Some("1".to_string()), keep_eol: false, json: false };
+
+        assert_eq!(defining_file(get("networking.hostName")), Ok(0));
+        assert_eq!(defining_file(get("services.nginx.enable")), Ok(3));
+        assert_eq!(defining_file(get("services.sshd")), Ok(2));
+        assert_eq!(defining_file(get("services.foo")), Ok(0));
+        assert_eq!(defining_file(set("services.nginx.virtualHosts")), Ok(3));
+        assert_eq!(defining_file(set("services.foo.enable")), Ok(2));
+        assert_eq!(defining_file(set("time.timeZone")), Ok(0));
+        assert_eq!(defining_file(get("services")),
+                   Err(format!("Path 'services' is defined in several files ({}, {}).",
+                               files[2].0.display(), files[3].0.display())));
+
+        fs::write(dir.join("hardware.nix"), "{ imports = [ ./missing.nix ]; }").unwrap();
+
+        assert!(imports::load_imports(&root, fs::read_to_string(&root).unwrap()).unwrap_err()
+                       .message().starts_with("Unable to read imported file"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_nix_eval() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("nixcfg-nix-eval-{}", process::id()));
+        let stub = dir.join("nix-instantiate");
+
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&stub, "#!/bin/sh\nprintf '%s' \"$5\" > expr.nix\n[ \"$1 $2 $3 $4\" = '--eval --json --strict -E' ] || exit 1\n\
+                          echo '{\"enable\":true,\"ports\":[80]}'\n").unwrap();
+        fs::set_permissions(&stub, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let nix = "{ lib, pkgs, x ? 1, ... }:\n\nlet port = 80; in\n\nwith lib; {\n  a = mkIf true { enable = true; ports = [ port ]; };\n}";
+        let get = |command: &FilePath| {
+            let command = Command::Get { path: "a".to_string(), output: OutputFormat::Nix, locate: false, eval: false,
+                                         nix_eval: true, nix_instantiate: command.to_string_lossy().into_owned() };
+            let options = Options { file: dir.join("configuration.nix"), ..Options::default() };
+
+            run_command(nix, command, &options)
+        };
+
+        assert_eq!(get(&stub), Ok("{\n  enable = true;\n  ports = [ 80 ];\n}".to_string()));
+        assert_eq!(fs::read_to_string(dir.join("expr.nix")).unwrap(),
+                   "({ lib, pkgs, x ? 1, ... }: let port = 80; in with lib; mkIf true { enable = true; ports = [ port ]; }) \
+                    { lib = (import <nixpkgs> { }).lib; pkgs = import <nixpkgs> { }; }");
+
+        // The default command is looked up in the PATH
+        let path = std::env::var_os("PATH").unwrap_or_default();
+        let mut paths = vec![dir.clone()];
+
+        paths.extend(std::env::split_paths(&path));
+        std::env::set_var("PATH", std::env::join_paths(paths).unwrap());
+
+        let result = get(FilePath::new("nix-instantiate"));
+
+        std::env::set_var("PATH", &path);
+
+        assert_eq!(result, Ok("{\n  enable = true;\n  ports = [ 80 ];\n}".to_string()));
+
+        fs::write(&stub, "#!/bin/sh\necho 'error: undefined variable' >&2\nexit 1\n").unwrap();
+
+        assert_eq!(get(&stub), Err(format!("Evaluation with '{}' failed:\nerror: undefined variable", stub.display())));
+        assert_eq!(get(&dir.join("missing")),
+                   Err(format!("Unable to find '{}'; make sure that Nix is installed.", dir.join("missing").display())));
+
+        fs::remove_dir_all(&dir).unwrap();
     }
 
     #[test]
@@ -304,7 +1475,8 @@ mod tests {
         for test_path in path.read_dir().unwrap() {
             let mut test_path = test_path.unwrap().path();
 
-            if test_path.file_name().unwrap().to_str().unwrap().ends_with(".expected.nix") {
+            // The corpus is tested by the library
+            if test_path.is_dir() || test_path.file_name().unwrap().to_str().unwrap().ends_with(".expected.nix") {
                 continue
             }
 
@@ -341,12 +1513,12 @@ mod tests {
                 // Perform replacement
                 let cmd = Command::Set {
                     path: pattern.to_string(),
-                    value: replace_by
+                    value: Some(replace_by),
+                    keep_eol: false,
+                    json: false
                 };
 
-                let mut result = given.to_string();
-
-                process(rnix::parse(given).unwrap(), cmd, &mut result).unwrap();
+                let result = run_command(given, cmd, &Options::default()).unwrap();
 
                 // Compare with expected output
                 assert_eq!(result.trim(), expected.trim());
@@ -354,12 +1526,15 @@ mod tests {
             } else {
                 // Test query
                 let cmd = Command::Get {
-                    path: pattern.to_string()
+                    path: pattern.to_string(),
+                    output: OutputFormat::Nix,
+                    locate: false,
+                    eval: false,
+                    nix_eval: false,
+                    nix_instantiate: String::new()
                 };
 
-                let mut result = String::new();
-
-                process(rnix::parse(given).unwrap(), cmd, &mut result).unwrap();
+                let result = run_command(given, cmd, &Options::default()).unwrap();
 
                 // Compare with expected output
                 assert_eq!(result, replace_by);
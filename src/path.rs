@@ -0,0 +1,742 @@
+//! Parsing and formatting of attribute paths, such as `services."nginx".enable`.
+
+use std::fmt;
+use std::str::FromStr;
+
+use rnix::SyntaxNode;
+use rnix::SyntaxKind::*;
+
+use error::Error;
+use json::nix_string;
+use super::{child, find_expr, ident_name, span_range, static_string};
+
+
+/// The name of an attribute in the syntax tree.
+#[derive(Clone, Debug)]
+pub enum AttrName {
+    /// A name known statically, such as `foo` or `"foo.bar"`.
+    Static(String),
+
+    /// A name computed at runtime, such as `${name}` or `"foo-${name}"`.
+    Dynamic(SyntaxNode)
+}
+
+impl AttrName {
+    /// Return whether the name is statically equal to the given part of a path.
+    pub fn matches(&self, part: &str) -> bool {
+        match *self {
+            AttrName::Static(ref name) => name == part,
+            AttrName::Dynamic(_) => false
+        }
+    }
+}
+
+/// Return the names making up the given attribute or attribute selection, such as
+/// `a."b".${c}` or `a.b.c`.
+pub fn attribute_names(node: &SyntaxNode) -> Vec<AttrName> {
+    let mut names = Vec::new();
+
+    for child in node.children() {
+        match child.kind() {
+            NODE_SELECT => names.extend(attribute_names(&child)),
+            _ => names.push(match ident_name(&child).or_else(|| static_string(&child)) {
+                Some(name) => AttrName::Static(name),
+                None => AttrName::Dynamic(child)
+            })
+        }
+    }
+
+    names
+}
+
+/// A segment of a path.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Segment {
+    /// The bindings of the top-level `let` expression, denoted by a leading `let`.
+    Let,
+
+    /// The attribute with the given name, such as `enable` or `"example.com"`.
+    Name(String),
+
+    /// All attributes of a set, denoted by `*`.
+    AnyName,
+
+    /// All values nested in a value, at any depth and including the value itself, denoted
+    /// by `**`.
+    AnyPath,
+
+    /// The element of a list at the given index, such as `[0]`, or `[-1]` for the last one.
+    Index(isize),
+
+    /// All elements of a list, denoted by `[*]`.
+    AnyItem,
+
+    /// The values matched so far (or their elements, for lists) for which the given
+    /// predicate holds, such as `[isNormalUser == true]`.
+    Filter(Predicate)
+}
+
+/// A predicate comparing the value at a path relative to a matched value with the given
+/// Nix expression.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Predicate {
+    pub path: Vec<Segment>,
+    pub negated: bool,
+    pub value: String
+}
+
+/// A parsed path, such as `services.nginx.enable` or `fileSystems[*].device`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Path {
+    source: String,
+    segments: Vec<Segment>
+}
+
+impl Path {
+    /// Parse the given path, with the syntax described in `parse_path`.
+    pub fn parse(path: &str) -> Result<Path, Error> {
+        let segments = parse_path(path).map_err(Error::InvalidPath)?;
+
+        Ok(Path { source: path.to_string(), segments })
+    }
+
+    /// Return whether the path may match several values.
+    pub fn has_wildcards(&self) -> bool {
+        has_wildcards(&self.segments)
+    }
+
+    /// Return the segments of the path.
+    pub(crate) fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+}
+
+impl FromStr for Path {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Path, Error> {
+        Path::parse(s)
+    }
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// Parse the given path into its segments, where attribute names are separated by dots
+/// and may be quoted (with the same escape sequences as Nix strings) to contain special
+/// characters, and may be followed by selectors between brackets.
+pub fn parse_path(path: &str) -> Result<Vec<Segment>, String> {
+    Parser { input: path, pos: 0 }.path(false)
+}
+
+/// Return whether the given path may match several values.
+pub fn has_wildcards(segments: &[Segment]) -> bool {
+    segments.iter().any(|segment| match *segment {
+        Segment::AnyName | Segment::AnyPath | Segment::AnyItem | Segment::Filter(_) => true,
+        Segment::Let | Segment::Name(_) | Segment::Index(_) => false
+    })
+}
+
+/// Return the attribute names of the given path, or `None` if it has other segments.
+pub fn names(segments: &[Segment]) -> Option<Vec<&str>> {
+    segments.iter()
+            .map(|segment| match *segment {
+                Segment::Name(ref name) => Some(name.as_str()),
+                _ => None
+            })
+            .collect()
+}
+
+/// A parser of paths.
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let ch = self.peek()?;
+
+        self.pos += ch.len_utf8();
+        Some(ch)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().map(|ch| ch.is_ascii_whitespace()).unwrap_or(false) {
+            self.pos += 1;
+        }
+    }
+
+    /// Return whether the given character ends a path nested in a predicate.
+    fn is_nested_end(ch: char) -> bool {
+        ch.is_ascii_whitespace() || ch == '=' || ch == '!' || ch == ']'
+    }
+
+    /// Parse a path, which ends at the end of the input, or at the end of its predicate if
+    /// it is nested.
+    fn path(&mut self, nested: bool) -> Result<Vec<Segment>, String> {
+        let mut segments = Vec::new();
+
+        loop {
+            let rest = &self.input[self.pos..];
+            let wildcard = if rest.starts_with("**") { "**" } else { "*" };
+            let is_wildcard = rest.starts_with(wildcard) &&
+                              rest[wildcard.len()..].chars()
+                                                    .next()
+                                                    .map(|ch| ch == '.' || ch == '[' || (nested && Self::is_nested_end(ch)))
+                                                    .unwrap_or(true);
+
+            if rest.starts_with('[') && segments.is_empty() {
+                // Lists may be indexed directly
+            } else if (rest == "let" || rest.starts_with("let.") || rest.starts_with("let[")) && segments.is_empty() && !nested {
+                // `let` is a keyword, so attributes with this name must be quoted
+                self.pos += 3;
+                segments.push(Segment::Let);
+            } else if is_wildcard {
+                self.pos += wildcard.len();
+                segments.push(if wildcard == "**" { Segment::AnyPath } else { Segment::AnyName });
+            } else {
+                segments.push(Segment::Name(self.name(nested)?));
+            }
+
+            while self.peek() == Some('[') {
+                segments.push(self.selector()?);
+            }
+
+            match self.peek() {
+                None => return Ok(segments),
+                Some('.') => self.pos += 1,
+                Some(ch) if nested && Self::is_nested_end(ch) => return Ok(segments),
+                Some(ch) => return Err(format!("Expected '.' at position {}, but found '{}'.", self.pos, ch))
+            }
+        }
+    }
+
+    /// Parse a quoted or unquoted attribute name.
+    fn name(&mut self, nested: bool) -> Result<String, String> {
+        let start = self.pos;
+        let mut name = String::new();
+
+        if self.peek() == Some('"') {
+            self.pos += 1;
+
+            loop {
+                match self.next() {
+                    Some('"') => return Ok(name),
+                    Some('\\') => match self.next() {
+                        Some('n') => name.push('\n'),
+                        Some('r') => name.push('\r'),
+                        Some('t') => name.push('\t'),
+                        Some(ch) => name.push(ch),
+                        None => return Err(format!("Unterminated attribute name at position {}.", start))
+                    },
+                    Some('$') if self.peek() == Some('{') =>
+                        return Err(format!("Attribute name at position {} is interpolated, and cannot be resolved.", start)),
+                    Some(ch) => name.push(ch),
+                    None => return Err(format!("Unterminated attribute name at position {}.", start))
+                }
+            }
+        }
+
+        while let Some(ch) = self.peek() {
+            if ch == '.' || ch == '[' || (nested && Self::is_nested_end(ch)) {
+                break
+            }
+
+            if ch == '"' || ch == ']' || self.input[self.pos..].starts_with("${") {
+                return Err(format!("Unexpected character '{}' at position {}; attribute names containing \
+                                    special characters must be quoted.", ch, self.pos))
+            }
+
+            name.push(ch);
+            self.pos += ch.len_utf8();
+        }
+
+        if name.is_empty() {
+            return Err(format!("Empty attribute name at position {}.", start))
+        }
+
+        Ok(name)
+    }
+
+    /// Parse a selector between brackets, such as `[0]`, `[*]` or `[enable == true]`.
+    fn selector(&mut self) -> Result<Segment, String> {
+        let start = self.pos;
+
+        self.pos += 1;
+        self.skip_whitespace();
+
+        let segment = match self.peek() {
+            Some('*') => {
+                self.pos += 1;
+                Segment::AnyItem
+            },
+
+            Some(ch) if ch == '-' || ch.is_ascii_digit() => {
+                let len = self.input[self.pos + 1..].find(|ch: char| !ch.is_ascii_digit())
+                                                    .unwrap_or(self.input.len() - self.pos - 1);
+                let index = &self.input[self.pos..self.pos + 1 + len];
+
+                self.pos += index.len();
+
+                match index.parse() {
+                    Ok(index) => Segment::Index(index),
+                    Err(_) => return Err(format!("Invalid index '{}' at position {}.", index, start + 1))
+                }
+            },
+
+            _ => {
+                let path = self.path(true)?;
+
+                self.skip_whitespace();
+
+                let negated = match &self.input[self.pos..] {
+                    rest if rest.starts_with("==") => false,
+                    rest if rest.starts_with("!=") => true,
+                    _ => return Err(format!("Expected '==' or '!=' at position {}.", self.pos))
+                };
+
+                self.pos += 2;
+
+                // The value ends at the first closing bracket that is not nested or quoted
+                let value_start = self.pos;
+                let mut depth = 0;
+
+                loop {
+                    match self.peek() {
+                        Some(']') if depth == 0 => break,
+                        Some('"') => {
+                            self.pos += 1;
+
+                            loop {
+                                match self.next() {
+                                    Some('"') | None => break,
+                                    Some('\\') => { self.next(); },
+                                    _ => ()
+                                }
+                            }
+                        },
+                        Some(ch) => {
+                            match ch {
+                                '[' | '{' | '(' => depth += 1,
+                                ']' | '}' | ')' => depth -= 1,
+                                _ => ()
+                            }
+
+                            self.pos += ch.len_utf8();
+                        },
+                        None => break
+                    }
+                }
+
+                let value = self.input[value_start..self.pos].trim();
+
+                if value.is_empty() {
+                    return Err(format!("Expected a value at position {}.", value_start))
+                }
+
+                Segment::Filter(Predicate { path, negated, value: value.to_string() })
+            }
+        };
+
+        self.skip_whitespace();
+
+        match self.next() {
+            Some(']') => Ok(segment),
+            _ => Err(format!("Unterminated selector at position {}.", start))
+        }
+    }
+}
+
+/// An entry defining a value, alongside the part of its key that comes after the path
+/// of the value.
+pub type Entry = (Vec<AttrName>, SyntaxNode);
+
+/// A value matched by a path, alongside its fully-qualified path.
+#[derive(Clone, Debug)]
+pub struct Match {
+    pub path: String,
+
+    /// The entries defining the value, alongside the part of their key that comes after
+    /// the path, like the ones returned by `collect_entries`.
+    pub entries: Vec<Entry>
+}
+
+impl Match {
+    /// Return the node of the value, or `None` if it is defined by several entries.
+    pub fn node(&self) -> Option<SyntaxNode> {
+        match self.entries.as_slice() {
+            &[(ref key, ref node)] if key.is_empty() => Some(node.clone()),
+            _ => None
+        }
+    }
+}
+
+/// Return all values matching the given path, starting from the top-level expression and
+/// only going through attribute sets and lists.
+pub fn resolve(root: &SyntaxNode, segments: &[Segment], content: &str) -> Result<Vec<Match>, Error> {
+    resolve_from(vec![root_match(root)], segments, content)
+}
+
+/// Return the attribute names computed at runtime in the values reached by the given path
+/// before it stopped matching, which may hide the value that was looked for.
+pub fn hidden_names(root: &SyntaxNode, segments: &[Segment], content: &str) -> Result<Vec<SyntaxNode>, Error> {
+    let mut matches = vec![root_match(root)];
+
+    for segment in segments {
+        let mut next = Vec::new();
+
+        for m in &matches {
+            next.extend(step(m.clone(), segment, content)?);
+        }
+
+        if next.is_empty() {
+            // Only attribute names can be hidden, not list elements
+            return match *segment {
+                Segment::Name(_) | Segment::AnyName => {
+                    let mut names = Vec::new();
+
+                    for m in &matches {
+                        names.extend(dynamic_names(&m.entries, content)?);
+                    }
+
+                    Ok(names)
+                },
+                _ => Ok(Vec::new())
+            }
+        }
+
+        matches = next;
+    }
+
+    Ok(Vec::new())
+}
+
+/// Return the match of the whole file, which starts every path.
+fn root_match(root: &SyntaxNode) -> Match {
+    // The root of the file also holds the comments around the expression
+    let entries = root.first_child().into_iter().map(|expr| (Vec::new(), expr)).collect();
+
+    Match { path: String::new(), entries }
+}
+
+fn resolve_from(mut matches: Vec<Match>, segments: &[Segment], content: &str) -> Result<Vec<Match>, Error> {
+    for segment in segments {
+        let mut next = Vec::new();
+
+        for m in matches {
+            next.extend(step(m, segment, content)?);
+        }
+
+        matches = next;
+    }
+
+    Ok(matches)
+}
+
+/// Return the attributes nested in the given value that are not attribute sets themselves,
+/// or the value itself if it is not an attribute set.
+pub fn flatten(m: Match, content: &str) -> Result<Vec<Match>, Error> {
+    let children = step(m.clone(), &Segment::AnyName, content)?;
+
+    if children.is_empty() && !m.path.is_empty() {
+        return Ok(vec![m])
+    }
+
+    let mut flattened = Vec::new();
+
+    for child in children {
+        flattened.extend(flatten(child, content)?);
+    }
+
+    Ok(flattened)
+}
+
+/// Return the values matched by the given segment in the given value.
+fn step(m: Match, segment: &Segment, content: &str) -> Result<Vec<Match>, Error> {
+    let join = |name: &str| if m.path.is_empty() {
+        format_name(name)
+    } else {
+        format!("{}.{}", m.path, format_name(name))
+    };
+
+    Ok(match *segment {
+        Segment::Let => {
+            let let_in = match m.node().and_then(|node| find_expr(&node, NODE_LET_IN)) {
+                Some(let_in) => let_in,
+                None => return Ok(Vec::new())
+            };
+
+            // Bindings are represented like the entries of an attribute set
+            vec![Match { path: "let".to_string(), entries: bindings(&let_in, content)? }]
+        },
+
+        Segment::Name(ref name) => attributes(&m.entries, content)?
+            .into_iter()
+            .filter(|(attr, _)| attr == name)
+            .map(|(attr, entries)| Match { path: join(&attr), entries })
+            .collect(),
+
+        Segment::AnyName => attributes(&m.entries, content)?
+            .into_iter()
+            .map(|(attr, entries)| Match { path: join(&attr), entries })
+            .collect(),
+
+        Segment::AnyPath => {
+            // Visit values depth-first, so that they are returned in the order of the source
+            let mut children = step(m.clone(), &Segment::AnyName, content)?;
+
+            children.extend(step(m.clone(), &Segment::AnyItem, content)?);
+
+            let mut matches = vec![m];
+
+            for child in children {
+                matches.extend(step(child, segment, content)?);
+            }
+
+            matches
+        },
+
+        Segment::Index(index) => {
+            let mut items = list_items(&m);
+            let index = if index < 0 { items.len() as isize + index } else { index };
+
+            if index < 0 || index as usize >= items.len() {
+                return Ok(Vec::new())
+            }
+
+            vec![Match { path: format!("{}[{}]", m.path, index), entries: vec![(Vec::new(), items.swap_remove(index as usize))] }]
+        },
+
+        Segment::AnyItem => list_items(&m)
+            .into_iter()
+            .enumerate()
+            .map(|(i, item)| Match { path: format!("{}[{}]", m.path, i), entries: vec![(Vec::new(), item)] })
+            .collect(),
+
+        Segment::Filter(ref predicate) => {
+            let holds = |m: &Match| -> Result<bool, Error> {
+                let is_equal = resolve_from(vec![m.clone()], &predicate.path, content)?
+                    .iter()
+                    .filter_map(Match::node)
+                    .any(|node| content[span_range(&node)].trim() == predicate.value);
+
+                Ok(is_equal != predicate.negated)
+            };
+
+            // Predicates on lists select their elements, and select the value itself otherwise
+            let candidates = if is_list(&m) {
+                step(m, &Segment::AnyItem, content)?
+            } else {
+                vec![m]
+            };
+            let mut matches = Vec::new();
+
+            for candidate in candidates {
+                if holds(&candidate)? {
+                    matches.push(candidate);
+                }
+            }
+
+            matches
+        }
+    })
+}
+
+/// Return the attributes defined by the given entries, alongside the entries defining
+/// each of them. Attributes whose name is computed at runtime are ignored.
+fn attributes(entries: &[Entry], content: &str) -> Result<Vec<(String, Vec<Entry>)>, Error> {
+    let mut attributes: Vec<(String, Vec<_>)> = Vec::new();
+    let mut add = |name: &AttrName, entry| if let AttrName::Static(ref name) = *name {
+        match attributes.iter_mut().find(|&&mut (ref attr, _)| attr == name) {
+            Some(&mut (_, ref mut entries)) => entries.push(entry),
+            None => attributes.push((name.clone(), vec![entry]))
+        }
+    };
+
+    for (key, value) in entries {
+        if let Some((name, rest)) = key.split_first() {
+            add(name, (rest.to_vec(), value.clone()));
+            continue
+        }
+
+        for set in sets(value) {
+            for (key, value) in bindings(&set, content)? {
+                if let Some((name, rest)) = key.split_first() {
+                    add(name, (rest.to_vec(), value));
+                }
+            }
+        }
+    }
+
+    Ok(attributes)
+}
+
+/// Return the attribute names computed at runtime that start the keys of the attributes
+/// defined by the given entries, as ignored by `attributes`.
+fn dynamic_names(entries: &[Entry], content: &str) -> Result<Vec<SyntaxNode>, Error> {
+    let mut names = Vec::new();
+
+    for (key, value) in entries {
+        if !key.is_empty() {
+            names.extend(key.first().cloned());
+            continue
+        }
+
+        for set in sets(value) {
+            for (key, _) in bindings(&set, content)? {
+                names.extend(key.first().cloned());
+            }
+        }
+    }
+
+    Ok(names.into_iter()
+            .filter_map(|name| match name {
+                AttrName::Dynamic(node) => Some(node),
+                AttrName::Static(_) => None
+            })
+            .collect())
+}
+
+/// Return the attribute sets denoted by the given value, looking through the lists passed to
+/// functions that merge them, such as `mkMerge [ ... ]`.
+fn sets(node: &SyntaxNode) -> Vec<SyntaxNode> {
+    if let Some(set) = find_expr(node, NODE_ATTR_SET) {
+        return vec![set]
+    }
+
+    let mut found = Vec::new();
+
+    match find_expr(node, NODE_LIST) {
+        Some(ref list) if list != node => for item in list.children() {
+            found.extend(sets(&item));
+        },
+        _ => ()
+    }
+
+    found
+}
+
+/// Return the entries of the given attribute set or `let` expression, alongside their key.
+fn bindings(node: &SyntaxNode, content: &str) -> Result<Vec<Entry>, Error> {
+    let mut bindings = Vec::new();
+
+    for entry in node.children() {
+        match entry.kind() {
+            NODE_KEY_VALUE => {
+                let key = attribute_names(&child(&entry, 0, content)?);
+
+                bindings.push((key, child(&entry, 1, content)?));
+            },
+
+            NODE_INHERIT => for ident in entry.children() {
+                if let Some(name) = ident_name(&ident) {
+                    bindings.push((vec![AttrName::Static(name)], ident));
+                }
+            },
+
+            _ => ()
+        }
+    }
+
+    Ok(bindings)
+}
+
+/// Return whether the given value is a list.
+fn is_list(m: &Match) -> bool {
+    m.node().and_then(|node| find_expr(&node, NODE_LIST)).is_some()
+}
+
+/// Return the elements of the list matched by the given value, if any.
+fn list_items(m: &Match) -> Vec<SyntaxNode> {
+    match m.node().and_then(|node| find_expr(&node, NODE_LIST)) {
+        Some(list) => list.children().collect(),
+        None => Vec::new()
+    }
+}
+
+/// Format the given attribute name, quoting it if it is not a valid identifier.
+pub fn format_name(name: &str) -> String {
+    const KEYWORDS: &[&str] = &["assert", "else", "if", "in", "inherit", "let", "rec", "then", "with"];
+
+    let is_ident = name.starts_with(|ch: char| ch.is_ascii_alphabetic() || ch == '_') &&
+                   name.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '_' || ch == '\'' || ch == '-');
+
+    if is_ident && !KEYWORDS.contains(&name) {
+        name.to_string()
+    } else {
+        nix_string(name)
+    }
+}
+
+/// Format the given path, quoting its parts that are not valid identifiers.
+pub fn format_path<S: AsRef<str>>(parts: &[S]) -> String {
+    parts.iter()
+         .map(|part| format_name(part.as_ref()))
+         .collect::<Vec<_>>()
+         .join(".")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_path() {
+        fn names(path: &str) -> Result<Vec<String>, String> {
+            parse_path(path).map(|segments| super::names(&segments).unwrap().iter().map(|name| name.to_string()).collect())
+        }
+
+        assert_eq!(names("a.b-c.d'"), Ok(vec!["a".to_string(), "b-c".to_string(), "d'".to_string()]));
+        assert_eq!(names(r#"services."nginx".virtualHosts."example.com""#),
+                   Ok(vec!["services".to_string(), "nginx".to_string(), "virtualHosts".to_string(), "example.com".to_string()]));
+        assert_eq!(names(r#""a\"b\\c\${d}""#), Ok(vec!["a\"b\\c${d}".to_string()]));
+
+        assert_eq!(parse_path("a..b"), Err("Empty attribute name at position 2.".to_string()));
+        assert_eq!(parse_path(""), Err("Empty attribute name at position 0.".to_string()));
+        assert_eq!(parse_path(r#""a"b"#), Err("Expected '.' at position 3, but found 'b'.".to_string()));
+        assert_eq!(parse_path(r#"a."b"#), Err("Unterminated attribute name at position 2.".to_string()));
+        assert_eq!(parse_path(r#"a."${b}""#), Err("Attribute name at position 2 is interpolated, and cannot be resolved.".to_string()));
+        assert!(parse_path("a.${b}").is_err());
+    }
+
+    #[test]
+    fn test_parse_selectors() {
+        let name = |name: &str| Segment::Name(name.to_string());
+
+        assert_eq!(parse_path("imports[0]"), Ok(vec![name("imports"), Segment::Index(0)]));
+        assert_eq!(parse_path("[-1][ * ].*"), Ok(vec![Segment::Index(-1), Segment::AnyItem, Segment::AnyName]));
+        assert_eq!(parse_path(r#"users.users.*[isNormalUser == true][ name != "a]b" ].home"#), Ok(vec![
+            name("users"), name("users"), Segment::AnyName,
+            Segment::Filter(Predicate { path: vec![name("isNormalUser")], negated: false, value: "true".to_string() }),
+            Segment::Filter(Predicate { path: vec![name("name")], negated: true, value: "\"a]b\"".to_string() }),
+            name("home")
+        ]));
+        assert_eq!(parse_path("a[b.c[0]==[ 1 ]]"), Ok(vec![
+            name("a"),
+            Segment::Filter(Predicate { path: vec![name("b"), name("c"), Segment::Index(0)], negated: false, value: "[ 1 ]".to_string() })
+        ]));
+
+        assert_eq!(parse_path("let.a.let"), Ok(vec![Segment::Let, name("a"), name("let")]));
+        assert_eq!(parse_path(r#""let".a"#), Ok(vec![name("let"), name("a")]));
+        assert_eq!(parse_path("**.a*.*"), Ok(vec![Segment::AnyPath, name("a*"), Segment::AnyName]));
+        assert_eq!(parse_path("a[0"), Err("Unterminated selector at position 1.".to_string()));
+        assert_eq!(parse_path("a[b]"), Err("Expected '==' or '!=' at position 3.".to_string()));
+        assert_eq!(parse_path("a[b == ]"), Err("Expected a value at position 6.".to_string()));
+        assert_eq!(parse_path("a[0]b"), Err("Expected '.' at position 4, but found 'b'.".to_string()));
+        assert_eq!(parse_path("b[\u{a0}*]"), Err("Expected '==' or '!=' at position 5.".to_string()));
+    }
+
+    #[test]
+    fn test_format_path() {
+        assert_eq!(format_path(&["a", "b-c", "example.com", "if", "${x}"]), r#"a.b-c."example.com"."if"."\${x}""#);
+    }
+}
@@ -0,0 +1,104 @@
+# Edit this configuration file to define what should be installed on
+# your system. Help is available in the configuration.nix(5) man page
+# and in the NixOS manual (accessible by running `nixos-help`).
+
+{ config, pkgs, lib, ... }:
+
+let
+  user = "alice";
+  unstable = import (fetchTarball https://github.com/NixOS/nixpkgs/archive/nixos-unstable.tar.gz) {
+    config = config.nixpkgs.config;
+  };
+in
+
+{
+  imports =
+    [ # Include the results of the hardware scan.
+      ./hardware-configuration.nix
+      <home-manager/nixos>
+    ];
+
+  # Use the systemd-boot EFI boot loader.
+  boot.loader.systemd-boot.enable = true;
+  boot.loader.efi.canTouchEfiVariables = true;
+  boot.kernelPackages = pkgs.linuxPackages_latest;
+  boot.kernel.sysctl."net.ipv4.ip_forward" = 1;
+
+  networking.hostName = "nixos"; # Define your hostname.
+  networking.networkmanager.enable = true;
+  networking.firewall = {
+    enable = true;
+    allowedTCPPorts = [ 22 80 443 ];
+    allowedUDPPortRanges = [ { from = 60000; to = 61000; } ];
+  };
+
+  time.timeZone = "Europe/Paris";
+
+  i18n.defaultLocale = "en_US.UTF-8";
+  i18n.extraLocaleSettings = {
+    LC_TIME = "fr_FR.UTF-8";
+  };
+
+  services.xserver = {
+    enable = true;
+    layout = "us";
+    xkbOptions = "eurosign:e,caps:escape";
+    displayManager.gdm.enable = true;
+    desktopManager.gnome.enable = true;
+  };
+
+  services.openssh = {
+    enable = true;
+    settings.PasswordAuthentication = false;
+    ports = [ 22 ];
+  };
+
+  services.nginx = lib.mkIf config.services.openssh.enable {
+    enable = true;
+    recommendedTlsSettings = true;
+    virtualHosts."example.com" = {
+      forceSSL = true;
+      enableACME = true;
+      root = "/var/www/example.com";
+      locations."/api".proxyPass = "http://127.0.0.1:${toString 8080}";
+    };
+  };
+
+  users.users.${user} = {
+    isNormalUser = true;
+    extraGroups = [ "wheel" "networkmanager" ]; # Enable ‘sudo’ for the user.
+    shell = pkgs.zsh;
+    openssh.authorizedKeys.keys = [
+      "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIJ alice@laptop"
+    ];
+  };
+
+  environment.systemPackages = with pkgs; [
+    vim
+    wget
+    git
+    unstable.firefox
+    (python3.withPackages (ps: with ps; [ requests numpy ]))
+  ];
+
+  environment.variables = rec {
+    EDITOR = "vim";
+    VISUAL = EDITOR;
+  };
+
+  programs.zsh.enable = true;
+  programs.gnupg.agent = { enable = true; enableSSHSupport = true; };
+
+  nixpkgs.config.allowUnfree = true;
+  nixpkgs.overlays = [
+    (self: super: {
+      hello = super.hello.overrideAttrs (old: { doCheck = false; });
+    })
+  ];
+
+  fonts.packages = [ pkgs.noto-fonts pkgs.fira-code ];
+
+  security.sudo.wheelNeedsPassword = false;
+
+  system.stateVersion = "23.11"; # Did you read the comment?
+}
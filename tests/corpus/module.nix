@@ -0,0 +1,56 @@
+{ config, lib, pkgs, ... }:
+
+with lib;
+
+let
+  cfg = config.services.backup;
+
+  script = pkgs.writeShellScript "backup" ''
+    set -eu
+    ${pkgs.rsync}/bin/rsync -a ${concatStringsSep " " cfg.paths} ${cfg.destination}
+    echo "done: ''${HOSTNAME}"
+  '';
+in
+{
+  options.services.backup = {
+    enable = mkEnableOption "periodic backups";
+
+    paths = mkOption {
+      type = types.listOf types.str;
+      default = [ "/home" "/etc" ];
+      description = "Paths to back up.";
+    };
+
+    destination = mkOption {
+      type = types.str;
+      example = "backup@server:/srv/backups";
+    };
+
+    interval = mkOption {
+      type = types.str;
+      default = "daily";
+    };
+  };
+
+  config = mkIf cfg.enable {
+    assertions = [
+      { assertion = cfg.paths != [ ]; message = "services.backup.paths must not be empty"; }
+    ];
+
+    systemd.services.backup = {
+      description = "Backup of ${toString (length cfg.paths)} paths";
+      serviceConfig = {
+        Type = "oneshot";
+        ExecStart = script;
+        Nice = 19;
+      };
+      path = [ pkgs.openssh ];
+    };
+
+    systemd.timers.backup = {
+      wantedBy = [ "timers.target" ];
+      timerConfig.OnCalendar = cfg.interval;
+      timerConfig.Persistent = true;
+    };
+  };
+}
@@ -0,0 +1,45 @@
+{ lib
+, stdenv
+, fetchFromGitHub
+, rustPlatform
+, pkg-config
+, openssl
+, darwin
+, withGui ? false
+}:
+
+rustPlatform.buildRustPackage rec {
+  pname = "example-tool";
+  version = "1.2.3";
+
+  src = fetchFromGitHub {
+    owner = "example";
+    repo = pname;
+    rev = "v${version}";
+    hash = "sha256-AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=";
+  };
+
+  cargoHash = "sha256-BBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBB=";
+
+  nativeBuildInputs = [ pkg-config ];
+  buildInputs = [ openssl ]
+    ++ lib.optionals stdenv.isDarwin [ darwin.apple_sdk.frameworks.Security ];
+
+  buildFeatures = lib.optional withGui "gui";
+
+  doCheck = !stdenv.isDarwin && version != "0.0.0";
+  checkFlags = [ "--skip=network" ];
+
+  postInstall = ''
+    install -Dm644 completions/${pname}.bash $out/share/bash-completion/completions/${pname}
+  '';
+
+  meta = with lib; {
+    description = "An example tool";
+    homepage = "https://github.com/example/example-tool";
+    license = with licenses; [ mit asl20 ];
+    maintainers = [ maintainers.alice ];
+    platforms = platforms.unix;
+    mainProgram = "example-tool";
+  };
+}
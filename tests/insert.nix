@@ -0,0 +1,11 @@
+# networking.firewall.allowedTCPPorts
+# [ 80 443 ]
+
+{ pkgs, ... }:
+
+{
+  environment.systemPackages = [ ];
+
+  networking.firewall.enable = true;
+  networking.hostName = "paradise";
+}